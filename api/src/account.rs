@@ -0,0 +1,340 @@
+//! Account details from the self-service api of the portal, served next to
+//! `loginOut` on the logout server. Like logging out, it answers for the
+//! account logged in from the requesting address, unless the request carries
+//! the credentials of an account.
+
+use reqwest::Error as ReqError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{error::Error, fmt::Display};
+
+use crate::auth::UserInfo;
+
+const USER_INFO_PATH: &str = "/userInfo";
+const ONLINE_LIST_PATH: &str = "/onlineList";
+const KICK_OFF_PATH: &str = "/kickOff";
+
+#[derive(Debug)]
+pub enum AccountError {
+    Request(ReqError),
+    /// the portal said no, usually because nobody is logged in from here
+    Rejected(String),
+    InvalidResponse(Value),
+}
+
+impl Display for AccountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "{}", e),
+            Self::Rejected(msg) => write!(f, "Rejected: {}", msg),
+            Self::InvalidResponse(v) => write!(f, "Invalid Response: {}", v),
+        }
+    }
+}
+
+impl Error for AccountError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountInfo {
+    /// name of the subscribed package
+    pub package: Option<String>,
+    /// remaining balance in fen, negative when in arrears
+    pub balance: Option<i64>,
+    /// traffic used in the current billing period, in MiB
+    pub used_mb: Option<u64>,
+    /// traffic included in the package, none when unlimited
+    pub quota_mb: Option<u64>,
+    /// when the package expires, as the portal shows it
+    pub expires: Option<String>,
+    /// devices logged in with the account, this one included
+    pub devices: Vec<OnlineDevice>,
+}
+
+impl AccountInfo {
+    /// Traffic left in the package, none when unlimited or unknown.
+    pub fn remaining_mb(&self) -> Option<u64> {
+        Some(
+            self.quota_mb?
+                .saturating_sub(self.used_mb.unwrap_or_default()),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OnlineDevice {
+    /// what [`kick_with`] takes to force the device offline
+    pub session: Option<String>,
+    pub ip: String,
+    pub mac: Option<String>,
+    /// as the portal shows it, e.g. `2024-03-01 08:02:11`
+    pub login_time: Option<String>,
+}
+
+/// The `data` of a successful response.
+fn parse_result(mut response: Value) -> Result<Value, AccountError> {
+    match response.get("result").and_then(Value::as_i64) {
+        Some(1) => Ok(response["data"].take()),
+        Some(_) => Err(AccountError::Rejected(
+            text(&response["msg"]).unwrap_or_default(),
+        )),
+        None => Err(AccountError::InvalidResponse(response)),
+    }
+}
+
+/// Numbers come as json numbers or as strings, depending on the field.
+fn number(v: &Value) -> Option<f64> {
+    v.as_f64()
+        .or_else(|| v.as_str()?.trim().parse().ok())
+        .filter(|n: &f64| n.is_finite())
+}
+
+fn text(v: &Value) -> Option<String> {
+    v.as_str()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+fn parse_user_info(response: Value) -> Result<AccountInfo, AccountError> {
+    let data = parse_result(response)?;
+    if !data.is_object() {
+        return Err(AccountError::InvalidResponse(data));
+    }
+    Ok(AccountInfo {
+        package: text(&data["packageName"]),
+        balance: number(&data["balance"]).map(|yuan| (yuan * 100.0).round() as i64),
+        used_mb: number(&data["usedFlow"]).map(|mb| mb.max(0.0).round() as u64),
+        quota_mb: number(&data["totalFlow"])
+            .filter(|mb| *mb > 0.0)
+            .map(|mb| mb.round() as u64),
+        expires: text(&data["expireDate"]),
+        devices: vec![],
+    })
+}
+
+fn parse_online_list(response: Value) -> Result<Vec<OnlineDevice>, AccountError> {
+    let data = parse_result(response)?;
+    let Some(devices) = data.as_array() else {
+        return Err(AccountError::InvalidResponse(data));
+    };
+    Ok(devices
+        .iter()
+        .filter_map(|device| {
+            Some(OnlineDevice {
+                session: text(&device["sessionId"]),
+                ip: text(&device["userIp"])?,
+                mac: text(&device["userMac"]),
+                login_time: text(&device["loginTime"]),
+            })
+        })
+        .collect())
+}
+
+/// Form fields telling the portal which account a request is about.
+fn credentials(user: &UserInfo) -> [(&'static str, &str); 3] {
+    [
+        ("username", user.id()),
+        ("password", user.password()),
+        ("operatorSuffix", user.suffix().to_str()),
+    ]
+}
+
+/// Oldest of `sessions` that can be kicked, the one least likely to be in
+/// use.
+pub fn oldest(sessions: &[OnlineDevice]) -> Option<&OnlineDevice> {
+    sessions
+        .iter()
+        .filter(|device| device.session.is_some())
+        // the portal's timestamps sort like strings, missing ones first
+        .min_by(|a, b| a.login_time.cmp(&b.login_time))
+}
+
+#[cfg(feature = "blocking")]
+pub fn account_info(base_url: &str) -> Result<AccountInfo, AccountError> {
+    let client = reqwest::blocking::Client::new();
+    let fetch = |path: &str| -> Result<Value, AccountError> {
+        client
+            .post(format!("{}{}", base_url, path))
+            .send()
+            .map_err(AccountError::Request)?
+            .json()
+            .map_err(AccountError::Request)
+    };
+    let mut info = parse_user_info(fetch(USER_INFO_PATH)?)?;
+    info.devices = parse_online_list(fetch(ONLINE_LIST_PATH)?)?;
+    Ok(info)
+}
+
+/// Sessions of `user`, which works without being logged in from here.
+#[cfg(feature = "blocking")]
+pub fn online_sessions(base_url: &str, user: &UserInfo) -> Result<Vec<OnlineDevice>, AccountError> {
+    let res = reqwest::blocking::Client::new()
+        .post(format!("{}{}", base_url, ONLINE_LIST_PATH))
+        .form(&credentials(user))
+        .send()
+        .map_err(AccountError::Request)?
+        .json()
+        .map_err(AccountError::Request)?;
+    parse_online_list(res)
+}
+
+/// Force the session of `user` with id `session` offline.
+#[cfg(feature = "blocking")]
+pub fn kick(base_url: &str, user: &UserInfo, session: &str) -> Result<(), AccountError> {
+    let mut form = credentials(user).to_vec();
+    form.push(("sessionId", session));
+    let res = reqwest::blocking::Client::new()
+        .post(format!("{}{}", base_url, KICK_OFF_PATH))
+        .form(&form)
+        .send()
+        .map_err(AccountError::Request)?
+        .json()
+        .map_err(AccountError::Request)?;
+    parse_result(res).map(|_| ())
+}
+
+#[cfg(feature = "async")]
+pub async fn account_info_async(base_url: &str) -> Result<AccountInfo, AccountError> {
+    account_info_with(&reqwest::Client::new(), base_url).await
+}
+
+/// [`account_info_async`] through `client`, e.g. one bound to an interface.
+#[cfg(feature = "async")]
+pub async fn account_info_with(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<AccountInfo, AccountError> {
+    let mut info = parse_user_info(fetch(client, base_url, USER_INFO_PATH).await?)?;
+    info.devices = parse_online_list(fetch(client, base_url, ONLINE_LIST_PATH).await?)?;
+    Ok(info)
+}
+
+/// Sessions of `user`, which works without being logged in from here.
+#[cfg(feature = "async")]
+pub async fn online_sessions_with(
+    client: &reqwest::Client,
+    base_url: &str,
+    user: &UserInfo,
+) -> Result<Vec<OnlineDevice>, AccountError> {
+    let res = client
+        .post(format!("{}{}", base_url, ONLINE_LIST_PATH))
+        .form(&credentials(user))
+        .send()
+        .await
+        .map_err(AccountError::Request)?
+        .json()
+        .await
+        .map_err(AccountError::Request)?;
+    parse_online_list(res)
+}
+
+/// Force the session of `user` with id `session` offline.
+#[cfg(feature = "async")]
+pub async fn kick_with(
+    client: &reqwest::Client,
+    base_url: &str,
+    user: &UserInfo,
+    session: &str,
+) -> Result<(), AccountError> {
+    let mut form = credentials(user).to_vec();
+    form.push(("sessionId", session));
+    let res = client
+        .post(format!("{}{}", base_url, KICK_OFF_PATH))
+        .form(&form)
+        .send()
+        .await
+        .map_err(AccountError::Request)?
+        .json()
+        .await
+        .map_err(AccountError::Request)?;
+    parse_result(res).map(|_| ())
+}
+
+#[cfg(feature = "async")]
+async fn fetch(
+    client: &reqwest::Client,
+    base_url: &str,
+    path: &str,
+) -> Result<Value, AccountError> {
+    client
+        .post(format!("{}{}", base_url, path))
+        .send()
+        .await
+        .map_err(AccountError::Request)?
+        .json()
+        .await
+        .map_err(AccountError::Request)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{oldest, parse_online_list, parse_user_info, AccountError, OnlineDevice};
+
+    fn fixture(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn user_info() {
+        let info = parse_user_info(fixture(include_str!("../fixtures/user_info.json"))).unwrap();
+        assert_eq!(info.package.as_deref(), Some("学生30元包月"));
+        assert_eq!(info.balance, Some(1250));
+        assert_eq!(info.used_mb, Some(10240));
+        assert_eq!(info.quota_mb, Some(51200));
+        assert_eq!(info.remaining_mb(), Some(40960));
+        assert_eq!(info.expires.as_deref(), Some("2024-07-01"));
+    }
+
+    #[test]
+    fn online_list() {
+        let devices =
+            parse_online_list(fixture(include_str!("../fixtures/online_list.json"))).unwrap();
+        assert_eq!(
+            devices,
+            [
+                OnlineDevice {
+                    session: Some("c0a8f3e1-0001".into()),
+                    ip: "10.102.3.4".into(),
+                    mac: Some("3C-7C-3F-11-22-33".into()),
+                    login_time: Some("2024-03-01 08:02:11".into()),
+                },
+                OnlineDevice {
+                    session: Some("c0a8f3e1-0002".into()),
+                    ip: "10.102.9.8".into(),
+                    mac: None,
+                    login_time: Some("2024-03-01 12:40:57".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn oldest_session() {
+        let mut devices =
+            parse_online_list(fixture(include_str!("../fixtures/online_list.json"))).unwrap();
+        assert_eq!(oldest(&devices).unwrap().ip, "10.102.3.4");
+        devices[0].session = None;
+        assert_eq!(oldest(&devices).unwrap().ip, "10.102.9.8");
+        devices[1].session = None;
+        assert!(oldest(&devices).is_none());
+    }
+
+    #[test]
+    fn rejected() {
+        let not_logged_in = || fixture(include_str!("../fixtures/not_logged_in.json"));
+        assert!(matches!(
+            parse_user_info(not_logged_in()),
+            Err(AccountError::Rejected(msg)) if msg == "用户未在线"
+        ));
+        assert!(matches!(
+            parse_online_list(not_logged_in()),
+            Err(AccountError::Rejected(_))
+        ));
+        assert!(matches!(
+            parse_user_info(fixture(r#"{"code": 200}"#)),
+            Err(AccountError::InvalidResponse(_))
+        ));
+    }
+}
@@ -1,15 +1,76 @@
 use core::fmt;
-use std::fmt::{Debug, Display, Formatter};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::portal::PortalContext;
+
+/// host probed to tell whether the internet is reachable, it has both A and
+/// AAAA records
+pub const PROBE_HOST: &str = "www.baidu.com";
+
 lazy_static! {
+    static ref REDIRECT_PATTERN: Regex =
+        Regex::new(r#"location(?:\.href[ \t]*=[ \t]*|\.replace\([ \t]*)["']([^"']+)["']"#).unwrap();
     static ref JS_URL_PATTERN: Regex =
         Regex::new(r#"<script.*?src="(.*?js/common.js).*?".*?>"#).unwrap();
 }
 
+/// Requests made while logging in, in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// portal redirect page
+    Index,
+    /// portal page and its `common.js`
+    Js,
+    Auth,
+    QuickAuth,
+}
+
+impl Step {
+    pub const ALL: [Step; 4] = [Step::Index, Step::Js, Step::Auth, Step::QuickAuth];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Step::Index => "index",
+            Step::Js => "js",
+            Step::Auth => "auth",
+            Step::QuickAuth => "quickauth",
+        }
+    }
+}
+
+static STEP_OBSERVER: OnceLock<fn(Step, Duration)> = OnceLock::new();
+
+/// Register a callback receiving the duration of every login step, failed
+/// ones included. Only the first registration takes effect.
+pub fn observe_steps(observer: fn(Step, Duration)) {
+    let _ = STEP_OBSERVER.set(observer);
+}
+
+/// Reports the time until it is dropped to the step observer.
+struct StepTimer(Step, Instant);
+
+impl StepTimer {
+    fn start(step: Step) -> Self {
+        Self(step, Instant::now())
+    }
+}
+
+impl Drop for StepTimer {
+    fn drop(&mut self) {
+        if let Some(observer) = STEP_OBSERVER.get() {
+            observer(self.0, self.1.elapsed());
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct IndexUrl {
     pub url: String,
@@ -17,22 +78,64 @@ pub struct IndexUrl {
     pub args: Vec<(String, String)>,
 }
 
+impl IndexUrl {
+    pub fn context(&self) -> PortalContext {
+        PortalContext::from_args(&self.args)
+    }
+}
+
 fn get_root_url(url: &str) -> String {
     let start = url.find("://").unwrap() + 3;
     let end = url[start..].find('/').unwrap() + start;
     url[..end].to_string()
 }
 
+/// `root` without its port, minding the colons of an IPv6 address like
+/// `http://[2001:da8::1]:801`.
+fn strip_port(root: &str) -> &str {
+    let host_start = root.find("://").map_or(0, |i| i + 3);
+    let host = &root[host_start..];
+    let port_colon = match host.rfind(']') {
+        Some(bracket) => host[bracket..].find(':').map(|i| i + bracket),
+        None => host.rfind(':'),
+    };
+    match port_colon {
+        Some(colon) => &root[..host_start + colon],
+        None => root,
+    }
+}
+
+/// The portal's redirect in a page, told apart from the redirects of a
+/// home router by telling which device it is about. Nothing about the
+/// portal's own address is assumed, it may be IPv4 or IPv6.
+pub fn portal_redirect(html: &str) -> Option<IndexUrl> {
+    let url = REDIRECT_PATTERN.captures(html)?.get(1)?.as_str();
+    let index = index_url(url)?;
+    let context = index.context();
+    (context.user_ip.is_some() || context.mac.is_some() || context.ac_name.is_some())
+        .then_some(index)
+}
+
 fn parse_index_page(html: &str) -> Option<IndexUrl> {
+    if let Some(index) = portal_redirect(html) {
+        return Some(index);
+    }
     let url = {
         let start = html.find('"')? + 1;
         let end = html[start..].find('"')? + start;
-        html[start..end].to_string()
+        &html[start..end]
     };
+    index_url(url)
+}
+
+fn index_url(url: &str) -> Option<IndexUrl> {
+    // a root to build the other urls on, see `get_root_url`
+    url[url.find("://")? + 3..].find('/')?;
+    let url = url.to_string();
     let root_url = get_root_url(&url);
     let args = {
         let start = url.find('?')? + 1;
-        url::form_urlencoded::parse(url[start..].as_bytes())
+        url::form_urlencoded::parse(&url.as_bytes()[start..])
             .into_owned()
             .collect()
     };
@@ -71,8 +174,23 @@ fn get_variable_value<'a>(js_code: &'a str, variable_name: &str) -> Option<&'a s
     None
 }
 
+// built outside of the request expression, the serializer isn't `Send` and
+// must not live across an await
+fn quickauth_url(index_url: IndexUrl, user: &UserInfo) -> String {
+    index_url.root
+        + "/quickauth.do?"
+        + url::form_urlencoded::Serializer::new(&mut String::new())
+            .extend_pairs(index_url.args)
+            .append_pair("userid", &(user.id.clone() + user.suffix.to_str()))
+            .append_pair("passwd", &user.password)
+            .finish()
+}
+
 pub struct AuthInfo {
+    /// logout server, which also serves the self-service api
     pub logout_url_root: String,
+    /// where to log the session out, as the portal's js has it
+    pub logout_url: String,
     pub auth_url: String,
     pub school_codes: String,
 }
@@ -81,13 +199,18 @@ fn get_js_auth_info(js: &str) -> AuthInfo {
     let auth_url = get_variable_value(js, "authApiUrl").unwrap();
     let logout_root = {
         let root = get_root_url(auth_url);
-        root[..root.rfind(':').unwrap()].to_string()
+        strip_port(&root).to_string()
     };
+    // older portals only have `authApiUrl`, their logout server is next to it
+    let logout_url = get_variable_value(js, "logoutApiUrl")
+        .map(str::to_string)
+        .unwrap_or_else(|| crate::logout::default_url(&logout_root));
     #[cfg(debug_assertions)]
-    println!("Logout Root: {}", logout_root);
+    println!("Logout Url: {}", logout_url);
 
     AuthInfo {
         logout_url_root: logout_root,
+        logout_url,
         auth_url: auth_url.into(),
         school_codes: get_variable_value(js, "authSchoolCodes")
             .unwrap()
@@ -95,11 +218,98 @@ fn get_js_auth_info(js: &str) -> AuthInfo {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Response fields holding credentials or tokens, matched case-insensitively
+/// as a part of the key.
+const SECRET_KEYS: [&str; 6] = ["password", "passwd", "pwd", "token", "secret", "cookie"];
+const REDACTED: &str = "<redacted>";
+
+/// Blank out the secrets of a response, anything under a secret key and any
+/// string the password shows up in.
+fn redact(value: &mut serde_json::Value, password: &str) {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    redact(value, password);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact(item, password)),
+        Value::String(s) if !password.is_empty() && s.contains(password) => {
+            *s = REDACTED.into();
+        }
+        _ => (),
+    }
+}
+
+/// What a successful login left behind, enough to log out of it later.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// root of the portal the login went through
+    pub portal: String,
+    /// redirect url the portal was found at
+    pub url: String,
+    /// query args of the redirect, e.g. the address of this device
+    pub args: Vec<(String, String)>,
+    /// logout server, which also serves the self-service api
+    pub logout_url_base: String,
+    /// where to log the session out
+    pub logout_url: String,
+    /// account with its operator suffix, e.g. `2108114040@yd`
+    pub account: String,
+    /// unix timestamp of the login
+    pub logged_in_at: u64,
+    /// what the first auth and quickauth answered, in order, with
+    /// credentials and tokens redacted
+    pub responses: Vec<serde_json::Value>,
+}
+
+impl Session {
+    /// Taken before `auth` consumes what it is built from.
+    fn start(index_url: &IndexUrl, auth_info: &AuthInfo, user: &UserInfo) -> Self {
+        Self {
+            portal: index_url.root.clone(),
+            url: index_url.url.clone(),
+            args: index_url.args.clone(),
+            logout_url_base: auth_info.logout_url_root.clone(),
+            logout_url: auth_info.logout_url.clone(),
+            account: user.id.clone() + user.suffix.to_str(),
+            logged_in_at: 0,
+            responses: vec![],
+        }
+    }
+
+    /// Keep an auth response, redacted first as the session ends up in the
+    /// config file.
+    fn record(&mut self, mut resp: serde_json::Value, user: &UserInfo) {
+        redact(&mut resp, &user.password);
+        self.responses.push(resp);
+    }
+
+    pub fn context(&self) -> PortalContext {
+        PortalContext::from_args(&self.args)
+    }
+
+    fn established(mut self) -> Self {
+        self.logged_in_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Suffix {
-    ChinaMobie,
+    ChinaMobile,
     ChinaUnicom,
     ChinaTelecom,
+    #[default]
     Local,
 }
 
@@ -118,14 +328,34 @@ impl Suffix {
     const CT: &'static str = "@dx";
     const LOCAL: &'static str = "@hsd";
 
-    pub fn to_str(&self) -> &str {
+    pub const ALL: [Suffix; 4] = [
+        Suffix::ChinaMobile,
+        Suffix::ChinaUnicom,
+        Suffix::ChinaTelecom,
+        Suffix::Local,
+    ];
+
+    pub fn to_str(&self) -> &'static str {
         match self {
-            Suffix::ChinaMobie => Self::CM,
+            Suffix::ChinaMobile => Self::CM,
             Suffix::ChinaUnicom => Self::CU,
             Suffix::ChinaTelecom => Self::CT,
             Suffix::Local => Self::LOCAL,
         }
     }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|suffix| suffix.to_str() == s)
+    }
+
+    /// Known suffixes joined for error messages, e.g. `@yd, @lt, @dx, @hsd`.
+    pub fn expected() -> String {
+        Self::ALL
+            .iter()
+            .map(|s| s.to_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 }
 
 impl<'de> Deserialize<'de> for Suffix {
@@ -134,24 +364,13 @@ impl<'de> Deserialize<'de> for Suffix {
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        match s.as_str() {
-            Self::CM => Ok(Self::ChinaMobie),
-            Self::CU => Ok(Self::ChinaUnicom),
-            Self::CT => Ok(Self::ChinaTelecom),
-            Self::LOCAL => Ok(Self::Local),
-            _ => Err(serde::de::Error::custom("Invalid Suffix")),
-        }
-    }
-}
-
-impl Debug for Suffix {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::ChinaMobie => write!(f, "ChinaMobie"),
-            Self::ChinaUnicom => write!(f, "ChinaUnicom"),
-            Self::ChinaTelecom => write!(f, "ChinaTelecom"),
-            Self::Local => write!(f, "Local"),
-        }
+        Self::parse(&s).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid suffix `{}`, expected one of {}",
+                s,
+                Self::expected()
+            ))
+        })
     }
 }
 
@@ -185,131 +404,365 @@ impl Display for AuthError {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl AuthError {
+    /// Why the portal refused, none when it wasn't the portal refusing.
+    pub fn failure(&self) -> Option<AuthFailure> {
+        match self {
+            AuthError::AuthFailed { msg } => Some(AuthFailure::classify(msg)),
+            _ => None,
+        }
+    }
+}
+
+/// Why the portal refused a login, told apart by the message it gave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthFailure {
+    WrongPassword,
+    /// locked after too many wrong passwords, every retry extends the lock
+    AccountLocked,
+    /// frozen, disabled or cancelled
+    AccountSuspended,
+    /// in arrears or the package ran out
+    InsufficientBalance,
+    /// the account has as many devices online as it may
+    DeviceLimit,
+    /// the account doesn't belong to the chosen operator
+    OperatorMismatch,
+    /// too many attempts, the portal wants us to slow down
+    RateLimited,
+    Unknown(String),
+}
+
+impl AuthFailure {
+    /// Keywords of each kind, checked in order so that e.g. a lockout after
+    /// too many wrong passwords is `AccountLocked` rather than `WrongPassword`
+    /// or `RateLimited`, whose words it shares.
+    const KEYWORDS: [(&'static [&'static str], AuthFailure); 6] = [
+        (&["锁定", "密码错误次数"], AuthFailure::AccountLocked),
+        (
+            &[
+                "密码错误",
+                "密码不正确",
+                "用户名或密码",
+                "账号或密码",
+                "用户不存在",
+            ],
+            AuthFailure::WrongPassword,
+        ),
+        (&["频繁", "稍后", "次数过多"], AuthFailure::RateLimited),
+        (
+            &["停用", "冻结", "禁用", "注销", "暂停使用"],
+            AuthFailure::AccountSuspended,
+        ),
+        (
+            &["欠费", "余额不足", "已到期", "已过期"],
+            AuthFailure::InsufficientBalance,
+        ),
+        (
+            &["运营商", "服务类型", "所选服务"],
+            AuthFailure::OperatorMismatch,
+        ),
+    ];
+
+    pub fn classify(msg: &str) -> Self {
+        // checked first, limit messages may mention other words
+        let device_limit = ["终端", "设备", "在线数"].iter().any(|s| msg.contains(s))
+            && ["上限", "超过", "超出", "限制"]
+                .iter()
+                .any(|s| msg.contains(s));
+        if device_limit {
+            return AuthFailure::DeviceLimit;
+        }
+        Self::KEYWORDS
+            .iter()
+            .find(|(words, _)| words.iter().any(|w| msg.contains(w)))
+            .map_or_else(
+                || AuthFailure::Unknown(msg.to_string()),
+                |(_, kind)| kind.clone(),
+            )
+    }
+
+    /// Retrying won't help until someone does something about it.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            AuthFailure::WrongPassword
+                | AuthFailure::AccountLocked
+                | AuthFailure::AccountSuspended
+                | AuthFailure::InsufficientBalance
+                | AuthFailure::OperatorMismatch
+        )
+    }
+
+    /// The credentials themselves are refused, retrying them only makes
+    /// matters worse.
+    pub fn is_credential(&self) -> bool {
+        matches!(
+            self,
+            AuthFailure::WrongPassword | AuthFailure::AccountLocked
+        )
+    }
+}
+
+impl Display for AuthFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthFailure::WrongPassword => write!(f, "账号或密码错误"),
+            AuthFailure::AccountLocked => write!(f, "密码错误次数过多，账号已锁定"),
+            AuthFailure::AccountSuspended => write!(f, "账号已停用"),
+            AuthFailure::InsufficientBalance => write!(f, "账号欠费或套餐已到期"),
+            AuthFailure::DeviceLimit => write!(f, "在线设备数已达上限"),
+            AuthFailure::OperatorMismatch => write!(f, "运营商与账号不符"),
+            AuthFailure::RateLimited => write!(f, "登录过于频繁"),
+            AuthFailure::Unknown(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct UserInfo {
     id: String,
     password: String,
     suffix: Suffix,
 }
 
+impl UserInfo {
+    pub fn new(id: impl Into<String>, password: impl Into<String>, suffix: Suffix) -> Self {
+        Self {
+            id: id.into(),
+            password: password.into(),
+            suffix,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub fn suffix(&self) -> Suffix {
+        self.suffix
+    }
+}
+
 #[cfg(feature = "async")]
 pub mod auth_async {
+    use std::{
+        net::{IpAddr, SocketAddr},
+        sync::Arc,
+    };
+
     use reqwest::Client;
 
-    use crate::tool::ping_async;
+    use crate::{
+        dns::{is_hijacked, Resolver},
+        tool::{ping_addr_async, Family, Reachability},
+    };
 
     use super::*;
 
-    pub async fn get_index_page(ping: bool) -> Result<IndexUrl, AuthError> {
-        if ping && ping_async("www.baidu.com", 80).await.is_ok() {
-            return Err(AuthError::Authed);
+    /// The way out to the portal. The default one follows the system's
+    /// routes, a bound one sends everything from the address of one
+    /// interface, e.g. the WAN side of a gateway. Names are resolved by its
+    /// [`Resolver`], the system's unless given another one.
+    #[derive(Debug, Clone, Default)]
+    pub struct Network {
+        client: Client,
+        local_address: Option<IpAddr>,
+        resolver: Resolver,
+    }
+
+    impl Network {
+        pub fn bind(local_address: IpAddr) -> Self {
+            Self {
+                local_address: Some(local_address),
+                ..Default::default()
+            }
+            .with_resolver(Resolver::default())
         }
 
-        let resp = reqwest::get("http://192.168.0.1")
-            .await
-            .map_err(AuthError::ReqError)?
-            .text()
-            .await
-            .map_err(AuthError::ReqError)?;
-        parse_index_page(&resp).ok_or(AuthError::InvalidResponse(Box::new(resp)))
-    }
+        /// Resolve names through `resolver`, the portal's and the probe's
+        /// included. A bound network sends the queries from its address too.
+        pub fn with_resolver(self, resolver: Resolver) -> Self {
+            let resolver = resolver.bound_to(self.local_address);
+            let mut builder = Client::builder().dns_resolver(Arc::new(resolver.clone()));
+            if let Some(local_address) = self.local_address {
+                builder = builder.local_address(local_address);
+            }
+            Self {
+                client: builder.build().unwrap(),
+                local_address: self.local_address,
+                resolver,
+            }
+        }
 
-    pub async fn get_auth_info(index_url: &IndexUrl) -> Result<AuthInfo, AuthError> {
-        let resp = reqwest::get(index_url.url.clone())
-            .await
-            .map_err(AuthError::ReqError)?
-            .text()
-            .await
-            .map_err(AuthError::ReqError)?;
-        let js_url =
-            get_js_url(&resp).ok_or_else(|| AuthError::InvalidResponse(Box::new(resp.clone())))?;
-        #[cfg(debug_assertions)]
-        println!("JS URL: {}", js_url);
+        pub fn client(&self) -> &Client {
+            &self.client
+        }
 
-        Ok(get_js_auth_info(
-            reqwest::get(index_url.root.clone() + js_url)
+        pub fn local_address(&self) -> Option<IpAddr> {
+            self.local_address
+        }
+
+        /// Whether the internet is reachable over IPv4, i.e. we are logged
+        /// in. IPv6 is left out, on a dual-stack campus it may be let through
+        /// while IPv4 still waits for the portal.
+        pub async fn is_online(&self) -> bool {
+            self.is_online_over(Family::V4).await
+        }
+
+        /// Whether the internet is reachable over `family`, never over the
+        /// other family of a bound network.
+        pub async fn is_online_over(&self, family: Family) -> bool {
+            self.probe(family).await == Reachability::Online
+        }
+
+        /// Probe the internet over `family`. An answer for the probe host
+        /// that can't be right gives the portal away without waiting for a
+        /// connection, which the portal might even accept in its place.
+        pub async fn probe(&self, family: Family) -> Reachability {
+            let Ok(addrs) = self.resolver.lookup(PROBE_HOST).await else {
+                return Reachability::Offline;
+            };
+            let Some(addr) = addrs.into_iter().find(|addr| Family::of(addr) == family) else {
+                return Reachability::Offline;
+            };
+            if is_hijacked(&addr) {
+                return Reachability::Hijacked(addr);
+            }
+            match ping_addr_async(SocketAddr::new(addr, 80), self.local_address).await {
+                Ok(()) => Reachability::Online,
+                Err(_) => Reachability::Offline,
+            }
+        }
+
+        pub async fn get_index_page(&self, ping: bool) -> Result<IndexUrl, AuthError> {
+            if ping && self.is_online().await {
+                return Err(AuthError::Authed);
+            }
+
+            let _timer = StepTimer::start(Step::Index);
+            let resp = self.get_text("http://192.168.0.1").await?;
+            parse_index_page(&resp).ok_or(AuthError::InvalidResponse(Box::new(resp)))
+        }
+
+        pub async fn get_auth_info(&self, index_url: &IndexUrl) -> Result<AuthInfo, AuthError> {
+            let _timer = StepTimer::start(Step::Js);
+            let resp = self.get_text(&index_url.url).await?;
+            let js_url = get_js_url(&resp)
+                .ok_or_else(|| AuthError::InvalidResponse(Box::new(resp.clone())))?;
+            #[cfg(debug_assertions)]
+            println!("JS URL: {}", js_url);
+
+            Ok(get_js_auth_info(
+                self.get_text(&(index_url.root.clone() + js_url))
+                    .await?
+                    .as_str(),
+            ))
+        }
+
+        pub async fn auth(
+            &self,
+            index_url: IndexUrl,
+            auth_info: AuthInfo,
+            user: &UserInfo,
+        ) -> Result<Session, AuthError> {
+            let client = &self.client;
+            let mut session = Session::start(&index_url, &auth_info, user);
+            // first auth
+            let timer = StepTimer::start(Step::Auth);
+            let resp = client
+                .post(auth_info.auth_url)
+                .form(&[
+                    ("campusCode", auth_info.school_codes.as_str()),
+                    ("username", user.id.as_str()),
+                    ("password", user.password.as_str()),
+                    ("operatorSuffix", user.suffix.to_str()),
+                ])
+                .send()
                 .await
                 .map_err(AuthError::ReqError)?
-                .text()
+                .json::<serde_json::Value>()
+                .await
+                .map_err(AuthError::ReqError)?;
+            #[cfg(debug_assertions)]
+            println!("{:?}", resp);
+
+            if let Some(code) = resp.get("code").and_then(|r| r.as_i64()) {
+                if code != 1 {
+                    return Err(AuthError::AuthFailed {
+                        msg: resp
+                            .get("msg")
+                            .and_then(|r| r.as_str())
+                            .unwrap_or_default()
+                            .to_owned(),
+                    });
+                }
+            } else {
+                return Err(AuthError::InvalidResponse(Box::new(resp)));
+            }
+            session.record(resp, user);
+            drop(timer);
+
+            // quick auth
+            let _timer = StepTimer::start(Step::QuickAuth);
+            let resp = client
+                .get(quickauth_url(index_url, user))
+                .send()
                 .await
                 .map_err(AuthError::ReqError)?
-                .as_str(),
-        ))
+                .json::<serde_json::Value>()
+                .await
+                .map_err(AuthError::ReqError)?;
+            #[cfg(debug_assertions)]
+            println!("{:?}", resp);
+            if let Some(str) = resp.get("code").and_then(|r| r.as_str()) {
+                if str == "0" {
+                    session.record(resp, user);
+                    Ok(session.established())
+                } else {
+                    Err(AuthError::AuthFailed {
+                        msg: resp
+                            .get("message")
+                            .and_then(|r| r.as_str())
+                            .unwrap_or_default()
+                            .to_owned(),
+                    })
+                }
+            } else {
+                Err(AuthError::InvalidResponse(Box::new(resp)))
+            }
+        }
+
+        async fn get_text(&self, url: &str) -> Result<String, AuthError> {
+            self.client
+                .get(url)
+                .send()
+                .await
+                .map_err(AuthError::ReqError)?
+                .text()
+                .await
+                .map_err(AuthError::ReqError)
+        }
+    }
+
+    pub async fn get_index_page(ping: bool) -> Result<IndexUrl, AuthError> {
+        Network::default().get_index_page(ping).await
+    }
+
+    pub async fn get_auth_info(index_url: &IndexUrl) -> Result<AuthInfo, AuthError> {
+        Network::default().get_auth_info(index_url).await
     }
 
     pub async fn auth(
         index_url: IndexUrl,
         auth_info: AuthInfo,
         user: &UserInfo,
-    ) -> Result<(), AuthError> {
-        let client = Client::new();
-        // first auth
-        let resp = client
-            .post(auth_info.auth_url)
-            .form(&[
-                ("campusCode", auth_info.school_codes.as_str()),
-                ("username", user.id.as_str()),
-                ("password", user.password.as_str()),
-                ("operatorSuffix", user.suffix.to_str()),
-            ])
-            .send()
-            .await
-            .map_err(AuthError::ReqError)?
-            .json::<serde_json::Value>()
-            .await
-            .map_err(AuthError::ReqError)?;
-        #[cfg(debug_assertions)]
-        println!("{:?}", resp);
-
-        if let Some(code) = resp.get("code").and_then(|r| r.as_i64()) {
-            if code != 1 {
-                return Err(AuthError::AuthFailed {
-                    msg: resp
-                        .get("msg")
-                        .and_then(|r| r.as_str())
-                        .unwrap_or_default()
-                        .to_owned(),
-                });
-            }
-        } else {
-            return Err(AuthError::InvalidResponse(Box::new(resp)));
-        }
-
-        // quick auth
-
-        let resp = client
-            .get(
-                index_url.root
-                    + "/quickauth.do?"
-                    + url::form_urlencoded::Serializer::new(&mut String::new())
-                        .extend_pairs(index_url.args)
-                        .append_pair("userid", &(user.id.clone() + user.suffix.to_str()))
-                        .append_pair("passwd", &user.password)
-                        .finish(),
-            )
-            .send()
-            .await
-            .map_err(AuthError::ReqError)?
-            .json::<serde_json::Value>()
-            .await
-            .map_err(AuthError::ReqError)?;
-        #[cfg(debug_assertions)]
-        println!("{:?}", resp);
-        if let Some(str) = resp.get("code").and_then(|r| r.as_str()) {
-            if str == "0" {
-                Ok(())
-            } else {
-                return Err(AuthError::AuthFailed {
-                    msg: resp
-                        .get("message")
-                        .and_then(|r| r.as_str())
-                        .unwrap_or_default()
-                        .to_owned(),
-                });
-            }
-        } else {
-            Err(AuthError::InvalidResponse(Box::new(resp)))
-        }
+    ) -> Result<Session, AuthError> {
+        Network::default().auth(index_url, auth_info, user).await
     }
 
     mod tests {
@@ -327,6 +780,29 @@ pub mod auth_async {
             println!("{:?}", get_index_page(true).await.unwrap());
         }
 
+        #[test]
+        async fn futures_are_send() {
+            fn assert_send<T: Send>(_: T) {}
+            let index_url = || super::IndexUrl {
+                url: "http://10.101.2.205/index.html".into(),
+                root: "http://10.101.2.205".into(),
+                args: vec![],
+            };
+            let auth_info = || super::AuthInfo {
+                logout_url_root: "http://10.101.2.205".into(),
+                logout_url: "http://10.101.2.205/loginOut".into(),
+                auth_url: "http://10.101.2.205:8080/auth".into(),
+                school_codes: "".into(),
+            };
+            let user = super::UserInfo::default();
+            assert_send(super::get_index_page(false));
+            assert_send(super::get_auth_info(&index_url()));
+            assert_send(super::auth(index_url(), auth_info(), &user));
+            let network = super::Network::bind([127, 0, 0, 1].into());
+            assert_send(network.is_online());
+            assert_send(network.auth(index_url(), auth_info(), &user));
+        }
+
         #[test]
         async fn auth_test() {
             logout_async("http://10.101.2.205").await.unwrap();
@@ -336,7 +812,7 @@ pub mod auth_async {
             let user = super::UserInfo {
                 id: "".to_string(),
                 password: "".to_string(),
-                suffix: super::Suffix::ChinaMobie,
+                suffix: super::Suffix::ChinaMobile,
             };
             super::auth(index_url, auth_info, &user).await.unwrap();
         }
@@ -353,6 +829,7 @@ pub mod auth_blocking {
         if ping_check && ping("www.baidu.com", 80).is_ok() {
             return Err(AuthError::Authed);
         }
+        let _timer = StepTimer::start(Step::Index);
         let resp = reqwest::blocking::get("http://192.168.0.1")
             .map_err(AuthError::ReqError)?
             .text()
@@ -362,6 +839,7 @@ pub mod auth_blocking {
     }
 
     pub fn get_auth_info(index_url: &IndexUrl) -> Result<AuthInfo, AuthError> {
+        let _timer = StepTimer::start(Step::Js);
         let resp = reqwest::blocking::get(index_url.url.clone())
             .map_err(AuthError::ReqError)?
             .text()
@@ -384,9 +862,11 @@ pub mod auth_blocking {
         index_url: IndexUrl,
         auth_info: AuthInfo,
         user: &UserInfo,
-    ) -> Result<(), AuthError> {
+    ) -> Result<Session, AuthError> {
         let client = reqwest::blocking::Client::new();
+        let mut session = Session::start(&index_url, &auth_info, user);
         // first auth
+        let timer = StepTimer::start(Step::Auth);
         let resp = client
             .post(auth_info.auth_url)
             .form(&[
@@ -414,17 +894,12 @@ pub mod auth_blocking {
         } else {
             return Err(AuthError::InvalidResponse(Box::new(resp)));
         }
+        session.record(resp, user);
+        drop(timer);
         // quick auth
+        let _timer = StepTimer::start(Step::QuickAuth);
         let resp = client
-            .get(
-                index_url.root
-                    + "/quickauth.do?"
-                    + url::form_urlencoded::Serializer::new(&mut String::new())
-                        .extend_pairs(index_url.args)
-                        .append_pair("userid", &(user.id.clone() + user.suffix.to_str()))
-                        .append_pair("passwd", user.password.as_str())
-                        .finish(),
-            )
+            .get(quickauth_url(index_url, user))
             .send()
             .map_err(AuthError::ReqError)?
             .json::<serde_json::Value>()
@@ -433,7 +908,8 @@ pub mod auth_blocking {
         println!("{:?}", resp);
         if let Some(str) = resp.get("code").and_then(|r| r.as_str()) {
             if str == "0" {
-                Ok(())
+                session.record(resp, user);
+                Ok(session.established())
             } else {
                 Err(AuthError::AuthFailed {
                     msg: resp
@@ -470,9 +946,115 @@ pub mod auth_blocking {
             let user = super::UserInfo {
                 id: "".to_string(),
                 password: "".to_string(),
-                suffix: super::Suffix::ChinaMobie,
+                suffix: super::Suffix::ChinaMobile,
             };
             auth(index_url, auth_info, &user).unwrap();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_js_auth_info, portal_redirect, AuthError, AuthFailure, IndexUrl, Session, Suffix,
+        UserInfo,
+    };
+
+    #[test]
+    fn logout_url_from_js() {
+        let js = r#"var authApiUrl = "http://10.101.2.205:8080/auth";
+            var authSchoolCodes = "htu";"#;
+        assert_eq!(
+            get_js_auth_info(js).logout_url,
+            "http://10.101.2.205/loginOut"
+        );
+        let js = format!(
+            r#"{} var logoutApiUrl = "http://10.101.2.206/api/logout";"#,
+            js
+        );
+        let info = get_js_auth_info(&js);
+        assert_eq!(info.logout_url, "http://10.101.2.206/api/logout");
+        assert_eq!(info.logout_url_root, "http://10.101.2.205");
+        let js = r#"var authApiUrl = "http://[2001:da8:5014::205]:8080/auth";
+            var authSchoolCodes = "htu";"#;
+        assert_eq!(
+            get_js_auth_info(js).logout_url_root,
+            "http://[2001:da8:5014::205]"
+        );
+    }
+
+    #[test]
+    fn portal_redirects() {
+        let page = |url: &str| format!(r#"<script>top.self.location.replace("{}")</script>"#, url);
+        let v4 = portal_redirect(&page(
+            "http://10.101.2.205/eportal/index.jsp?wlanuserip=10.102.3.4&wlanacname=HTU-AC-01",
+        ))
+        .unwrap();
+        assert_eq!(v4.root, "http://10.101.2.205");
+        let v6 = portal_redirect(&format!(
+            r#"<script>window.location.href = '{}';</script>"#,
+            "http://[2001:da8:5014::205]/eportal/index.jsp?wlanuserip=2001:da8:5014:3::4&mac=3c7c3f112233"
+        ))
+        .unwrap();
+        assert_eq!(v6.root, "http://[2001:da8:5014::205]");
+        assert_eq!(v6.context().user_ip.as_deref(), Some("2001:da8:5014:3::4"));
+        // a home router sending its admin page to the login form
+        assert!(portal_redirect(&page("http://192.168.0.1/login.html?lang=zh")).is_none());
+        assert!(portal_redirect("<html>It works!</html>").is_none());
+    }
+
+    #[test]
+    fn session_redacts_credentials() {
+        let index_url = IndexUrl {
+            url: "http://10.101.2.205/index.html".into(),
+            root: "http://10.101.2.205".into(),
+            args: vec![],
+        };
+        let auth_info = get_js_auth_info(
+            r#"var authApiUrl = "http://10.101.2.205:8080/auth"; var authSchoolCodes = "htu";"#,
+        );
+        let user = UserInfo::new("2108114040", "hunter22", Suffix::ChinaMobile);
+        let mut session = Session::start(&index_url, &auth_info, &user);
+        session.record(
+            serde_json::json!({
+                "code": "0",
+                "data": { "sessionId": "c0a8f3e1-0001", "accessToken": "abc", "echo": "pwd=hunter22" },
+                "Set-Cookie": "JSESSIONID=1",
+            }),
+            &user,
+        );
+        let saved = serde_json::to_string(&session).unwrap();
+        assert!(saved.contains("c0a8f3e1-0001"));
+        for secret in ["abc", "hunter22", "JSESSIONID"] {
+            assert!(!saved.contains(secret), "{}", secret);
+        }
+    }
+
+    #[test]
+    fn classify() {
+        let failed = |msg: &str| AuthError::AuthFailed { msg: msg.into() }.failure();
+        let cases = [
+            ("终端数量已达上限", AuthFailure::DeviceLimit),
+            ("在线数超过限制，请先下线其他设备", AuthFailure::DeviceLimit),
+            ("密码错误", AuthFailure::WrongPassword),
+            ("密码错误次数过多，账号已锁定", AuthFailure::AccountLocked),
+            ("账号已被锁定，请30分钟后再试", AuthFailure::AccountLocked),
+            ("登录次数过多，请稍后再试", AuthFailure::RateLimited),
+            ("该账号已停用", AuthFailure::AccountSuspended),
+            ("用户已欠费，请充值", AuthFailure::InsufficientBalance),
+            ("所选服务与用户不匹配", AuthFailure::OperatorMismatch),
+            ("认证请求过于频繁", AuthFailure::RateLimited),
+        ];
+        for (msg, kind) in cases {
+            assert_eq!(failed(msg), Some(kind), "{}", msg);
+        }
+        let unknown = failed("系统维护中").unwrap();
+        assert_eq!(unknown, AuthFailure::Unknown("系统维护中".into()));
+        assert_eq!(unknown.to_string(), "系统维护中");
+        assert!(!unknown.is_permanent());
+        assert!(AuthFailure::WrongPassword.is_permanent());
+        assert!(AuthFailure::AccountLocked.is_credential());
+        assert!(!AuthFailure::RateLimited.is_permanent());
+        assert_eq!(AuthError::Authed.failure(), None);
+    }
+}
@@ -0,0 +1,358 @@
+//! Name resolution that doesn't have to go through the system's DNS, which
+//! a captive portal tends to hijack or leave hanging until we log in. Names
+//! can be pinned to addresses, e.g. the portal's, and the rest asked of
+//! specific DNS servers with a short timeout.
+
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const DNS_PORT: u16 = 53;
+/// how long a server gets to answer, the system's resolver included
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    /// asked in order, the system's resolver is used when empty
+    servers: Vec<SocketAddr>,
+    /// names answered without asking anyone
+    hosts: HashMap<String, Vec<IpAddr>>,
+    /// address the queries are sent from, the system's resolver can't be
+    /// told one
+    local_address: Option<IpAddr>,
+}
+
+impl Resolver {
+    /// Ask `servers` in order, those without a port on port 53.
+    pub fn with_servers(mut self, servers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.servers = servers.into_iter().collect();
+        self
+    }
+
+    /// Resolve `host` to `addrs` without asking anyone.
+    pub fn with_host(mut self, host: &str, addrs: Vec<IpAddr>) -> Self {
+        self.hosts.insert(host.to_ascii_lowercase(), addrs);
+        self
+    }
+
+    /// Send the queries from `local_address`, e.g. the WAN side of a gateway,
+    /// so that they leave the way the rest of the traffic does.
+    pub fn bound_to(mut self, local_address: Option<IpAddr>) -> Self {
+        self.local_address = local_address;
+        self
+    }
+
+    /// Parse a server as the config has it, `223.5.5.5`, `223.5.5.5:5353`
+    /// or `[2400:3200::1]:53`.
+    pub fn parse_server(server: &str) -> Option<SocketAddr> {
+        server
+            .parse()
+            .ok()
+            .or_else(|| Some(SocketAddr::new(server.parse().ok()?, DNS_PORT)))
+    }
+
+    /// Addresses of `host`, an address is its own answer.
+    #[cfg(feature = "async")]
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(addr) = host.parse() {
+            return Ok(vec![addr]);
+        }
+        if let Some(addrs) = self.hosts.get(&host.to_ascii_lowercase()) {
+            return Ok(addrs.clone());
+        }
+        if self.servers.is_empty() {
+            let addrs = tokio::time::timeout(TIMEOUT, tokio::net::lookup_host((host, 0)))
+                .await
+                .map_err(|_| Error::new(ErrorKind::TimedOut, "system resolver timed out"))??;
+            return Ok(addrs.map(|addr| addr.ip()).collect());
+        }
+        let mut last_error = None;
+        for server in &self.servers {
+            let (v4, v6) = tokio::join!(
+                query_server(*server, self.local_address, host, TYPE_A),
+                query_server(*server, self.local_address, host, TYPE_AAAA)
+            );
+            match (v4, v6) {
+                (Err(e), Err(_)) => last_error = Some(e),
+                (v4, v6) => {
+                    return Ok(v4
+                        .unwrap_or_default()
+                        .into_iter()
+                        .chain(v6.unwrap_or_default())
+                        .collect())
+                }
+            }
+        }
+        Err(last_error.unwrap())
+    }
+}
+
+#[cfg(feature = "async")]
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            // the port is filled in from the url
+            let addrs: reqwest::dns::Addrs =
+                Box::new(addrs.into_iter().map(|addr| SocketAddr::new(addr, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether an answer for a public name points somewhere it can't be, i.e.
+/// the DNS was hijacked, usually by a captive portal sending every name to
+/// itself.
+pub fn is_hijacked(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => {
+            let [a, b, ..] = addr.octets();
+            addr.is_private()
+                || addr.is_loopback()
+                || addr.is_link_local()
+                || addr.is_unspecified()
+                || addr.is_broadcast()
+                // shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(addr) => {
+            let first = addr.segments()[0];
+            addr.is_loopback()
+                || addr.is_unspecified()
+                // unique local, fc00::/7
+                || first & 0xfe00 == 0xfc00
+                // link local, fe80::/10
+                || first & 0xffc0 == 0xfe80
+                || addr.to_ipv4_mapped().is_some_and(|v4| is_hijacked(&v4.into()))
+        }
+    }
+}
+
+/// Address to send queries to `server` from, `local` if given.
+fn bind_addr(server: SocketAddr, local: Option<IpAddr>) -> Result<SocketAddr> {
+    match (server, local) {
+        (_, Some(local)) if local.is_ipv4() == server.is_ipv4() => Ok((local, 0).into()),
+        (_, Some(local)) => Err(Error::new(
+            ErrorKind::AddrNotAvailable,
+            format!("can't reach {} from {}", server, local),
+        )),
+        (SocketAddr::V4(_), None) => Ok((Ipv4Addr::UNSPECIFIED, 0).into()),
+        (SocketAddr::V6(_), None) => Ok((Ipv6Addr::UNSPECIFIED, 0).into()),
+    }
+}
+
+#[cfg(feature = "async")]
+async fn query_server(
+    server: SocketAddr,
+    local: Option<IpAddr>,
+    host: &str,
+    qtype: u16,
+) -> Result<Vec<IpAddr>> {
+    use tokio::{net::UdpSocket, time::timeout};
+
+    let socket = UdpSocket::bind(bind_addr(server, local)?).await?;
+    socket.connect(server).await?;
+    let id = query_id();
+    socket.send(&query(id, host, qtype)?).await?;
+    let mut buffer = [0; 1500];
+    timeout(TIMEOUT, async {
+        loop {
+            let len = socket.recv(&mut buffer).await?;
+            // stray answers to earlier queries are skipped
+            if len >= 2 && buffer[..2] == id.to_be_bytes() {
+                return parse_response(&buffer[..len], qtype);
+            }
+        }
+    })
+    .await
+    .map_err(|_| Error::new(ErrorKind::TimedOut, format!("{} didn't answer", server)))?
+}
+
+#[cfg(feature = "async")]
+fn query_id() -> u16 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16 ^ (d.subsec_nanos() >> 16) as u16)
+        .unwrap_or_default()
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// A recursive query for `host`, in wire format.
+fn query(id: u16, host: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(host.len() + 18);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid host name `{}`", host),
+            ));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(packet)
+}
+
+/// Position after the name starting at `pos`, a compressed name ends at its
+/// pointer.
+fn skip_name(packet: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *packet.get(pos).ok_or_else(|| invalid("truncated name"))?;
+        match len {
+            0 => return Ok(pos + 1),
+            len if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Result<u16> {
+    packet
+        .get(pos..pos + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid("truncated record"))
+}
+
+/// Addresses of type `qtype` in the answers of a response.
+fn parse_response(packet: &[u8], qtype: u16) -> Result<Vec<IpAddr>> {
+    let flags = read_u16(packet, 2)?;
+    match flags & 0x000f {
+        0 => (),
+        3 => return Err(Error::new(ErrorKind::NotFound, "no such name")),
+        rcode => return Err(invalid(&format!("server failed with rcode {}", rcode))),
+    }
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(packet, pos)? + 4;
+    }
+    let mut addrs = vec![];
+    for _ in 0..answers {
+        pos = skip_name(packet, pos)?;
+        let rtype = read_u16(packet, pos)?;
+        let len = read_u16(packet, pos + 8)? as usize;
+        pos += 10;
+        let data = packet
+            .get(pos..pos + len)
+            .ok_or_else(|| invalid("truncated record"))?;
+        pos += len;
+        // CNAMEs come along with the addresses they point to
+        if rtype != qtype {
+            continue;
+        }
+        match data.len() {
+            4 => addrs.push(IpAddr::from(<[u8; 4]>::try_from(data).unwrap())),
+            16 => addrs.push(IpAddr::from(<[u8; 16]>::try_from(data).unwrap())),
+            _ => return Err(invalid("address of unexpected length")),
+        }
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{bind_addr, is_hijacked, parse_response, query, Resolver, TYPE_A, TYPE_AAAA};
+
+    /// The answer to `query(0x1234, "www.baidu.com", TYPE_A)`, a CNAME to
+    /// `www.a.shifen.com` and its address.
+    fn answer(rcode: u8) -> Vec<u8> {
+        let mut packet = query(0x1234, "www.baidu.com", TYPE_A).unwrap();
+        packet[2] = 0x81;
+        packet[3] = 0x80 | rcode;
+        packet[7] = 2;
+        // CNAME, name pointing at the question
+        packet.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 15]);
+        packet.extend_from_slice(b"\x03www\x01a\x06shifen\xc0\x16");
+        // A, name pointing at the CNAME target
+        packet.extend_from_slice(&[0xc0, 43, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4]);
+        packet.extend_from_slice(&[110, 242, 68, 3]);
+        packet
+    }
+
+    #[test]
+    fn query_format() {
+        let packet = query(0x1234, "www.baidu.com", TYPE_AAAA).unwrap();
+        assert_eq!(
+            packet,
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x03www\x05baidu\x03com\x00\x00\x1c\x00\x01"
+        );
+        assert!(query(1, "www..com", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn response() {
+        let packet = answer(0);
+        assert_eq!(
+            parse_response(&packet, TYPE_A).unwrap(),
+            [IpAddr::from([110, 242, 68, 3])]
+        );
+        assert!(parse_response(&packet, TYPE_AAAA).unwrap().is_empty());
+        assert_eq!(
+            parse_response(&answer(3), TYPE_A).unwrap_err().kind(),
+            std::io::ErrorKind::NotFound
+        );
+        assert!(parse_response(&packet[..packet.len() - 2], TYPE_A).is_err());
+    }
+
+    #[test]
+    fn hijacked() {
+        for addr in [
+            "10.101.2.205",
+            "172.16.0.1",
+            "192.168.0.1",
+            "100.64.0.1",
+            "fd00::1",
+        ] {
+            assert!(is_hijacked(&addr.parse().unwrap()), "{}", addr);
+        }
+        for addr in ["110.242.68.3", "100.128.0.1", "240e:83:205::1"] {
+            assert!(!is_hijacked(&addr.parse().unwrap()), "{}", addr);
+        }
+    }
+
+    #[test]
+    fn bound_queries() {
+        let server = Resolver::parse_server("223.5.5.5").unwrap();
+        let wan: IpAddr = "10.102.3.4".parse().unwrap();
+        assert_eq!(bind_addr(server, Some(wan)).unwrap(), (wan, 0).into());
+        assert!(bind_addr(server, None).unwrap().ip().is_unspecified());
+        // a v4 WAN can't reach a v6 server
+        let server = Resolver::parse_server("[2400:3200::1]:53").unwrap();
+        assert!(bind_addr(server, Some(wan)).is_err());
+    }
+
+    #[test]
+    fn parse_server() {
+        assert_eq!(
+            Resolver::parse_server("223.5.5.5"),
+            Some(([223, 5, 5, 5], 53).into())
+        );
+        assert_eq!(
+            Resolver::parse_server("[2400:3200::1]:5353").map(|addr| addr.port()),
+            Some(5353)
+        );
+        assert_eq!(Resolver::parse_server("dns.alidns.com"), None);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod account;
 pub mod auth;
+pub mod dns;
 pub mod logout;
+pub mod portal;
 pub mod tool;
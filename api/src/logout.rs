@@ -6,6 +6,8 @@ use std::{error::Error, fmt::Display};
 pub enum LogoutError {
     JSON(Value),
     Request(ReqError),
+    /// there is no session to log out of
+    NotLoggedIn,
 }
 
 impl Display for LogoutError {
@@ -17,17 +19,32 @@ impl Display for LogoutError {
             Self::Request(e) => {
                 write!(f, "{}", e)
             }
+            Self::NotLoggedIn => write!(f, "Not logged in"),
         }
     }
 }
 
 impl Error for LogoutError {}
 
+/// Logout endpoint of the server at `base_url`, for portals whose js doesn't
+/// name one.
+pub fn default_url(base_url: &str) -> String {
+    format!("{}/loginOut", base_url)
+}
+
 fn parse_result(response: Value) -> Result<(), LogoutError> {
-    if let Some(1) = response.get("result").and_then(|r| r.as_i64()) {
-        Ok(())
-    } else {
-        Err(LogoutError::JSON(response))
+    match response.get("result").and_then(|r| r.as_i64()) {
+        Some(1) => Ok(()),
+        Some(_)
+            if response["msg"].as_str().is_some_and(|msg| {
+                ["未在线", "不在线", "未登录"]
+                    .iter()
+                    .any(|s| msg.contains(s))
+            }) =>
+        {
+            Err(LogoutError::NotLoggedIn)
+        }
+        _ => Err(LogoutError::JSON(response)),
     }
 }
 
@@ -35,7 +52,7 @@ fn parse_result(response: Value) -> Result<(), LogoutError> {
 pub fn logout(base_url: &str) -> Result<(), LogoutError> {
     let client = reqwest::blocking::Client::new();
     let res: Value = client
-        .post(format!("{}/loginOut", base_url))
+        .post(default_url(base_url))
         .send()
         .map_err(LogoutError::Request)?
         .json()
@@ -45,9 +62,25 @@ pub fn logout(base_url: &str) -> Result<(), LogoutError> {
 
 #[cfg(feature = "async")]
 pub async fn logout_async(base_url: &str) -> Result<(), LogoutError> {
-    let client = reqwest::Client::new();
+    logout_with(&reqwest::Client::new(), base_url).await
+}
+
+/// [`logout_async`] through `client`, e.g. one bound to an interface.
+#[cfg(feature = "async")]
+pub async fn logout_with(client: &reqwest::Client, base_url: &str) -> Result<(), LogoutError> {
+    logout_session_with(client, Some(&default_url(base_url))).await
+}
+
+/// Log out at `endpoint`, the logout url remembered from the login, none
+/// when there is no session.
+#[cfg(feature = "async")]
+pub async fn logout_session_with(
+    client: &reqwest::Client,
+    endpoint: Option<&str>,
+) -> Result<(), LogoutError> {
+    let endpoint = endpoint.ok_or(LogoutError::NotLoggedIn)?;
     let res: Value = client
-        .post(format!("{}/loginOut", base_url))
+        .post(endpoint)
         .send()
         .await
         .map_err(LogoutError::Request)?
@@ -58,6 +91,21 @@ pub async fn logout_async(base_url: &str) -> Result<(), LogoutError> {
 }
 
 mod tests {
+    #[test]
+    fn not_logged_in() {
+        let not_logged_in =
+            serde_json::from_str(include_str!("../fixtures/not_logged_in.json")).unwrap();
+        assert!(matches!(
+            super::parse_result(not_logged_in),
+            Err(super::LogoutError::NotLoggedIn)
+        ));
+        let rejected = serde_json::json!({ "result": 0, "msg": "系统繁忙" });
+        assert!(matches!(
+            super::parse_result(rejected),
+            Err(super::LogoutError::JSON(_))
+        ));
+    }
+
     #[test]
     #[cfg(feature = "blocking")]
     fn test_logout() {
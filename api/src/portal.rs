@@ -0,0 +1,97 @@
+//! What the portal tells about this device in the query of its redirect,
+//! e.g. `wlanuserip=10.102.3.4&wlanacname=HTU-AC-01&mac=3c7c3f112233`.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortalContext {
+    /// address of this device as the portal sees it
+    pub user_ip: Option<String>,
+    pub mac: Option<String>,
+    /// access controller the device is behind
+    pub ac_name: Option<String>,
+    pub ac_ip: Option<String>,
+    pub vlan: Option<String>,
+    pub ssid: Option<String>,
+    /// parameters without a field of their own
+    #[serde(default)]
+    pub extras: BTreeMap<String, String>,
+}
+
+impl PortalContext {
+    pub fn from_args(args: &[(String, String)]) -> Self {
+        let mut context = Self::default();
+        for (key, value) in args {
+            if value.is_empty() {
+                continue;
+            }
+            let field = match key.to_ascii_lowercase().as_str() {
+                "wlanuserip" | "userip" => &mut context.user_ip,
+                "mac" | "usermac" | "wlanusermac" => &mut context.mac,
+                "wlanacname" | "acname" => &mut context.ac_name,
+                "wlanacip" | "acip" | "nasip" => &mut context.ac_ip,
+                "vlan" | "vlanid" => &mut context.vlan,
+                "ssid" => &mut context.ssid,
+                _ => {
+                    context.extras.insert(key.clone(), value.clone());
+                    continue;
+                }
+            };
+            field.get_or_insert_with(|| value.clone());
+        }
+        context
+    }
+}
+
+impl Display for PortalContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let known = [
+            ("ip", &self.user_ip),
+            ("mac", &self.mac),
+            ("ac", &self.ac_name),
+            ("ac_ip", &self.ac_ip),
+            ("vlan", &self.vlan),
+            ("ssid", &self.ssid),
+        ];
+        let mut first = true;
+        let pairs = known
+            .iter()
+            .filter_map(|(key, value)| Some((*key, value.as_deref()?)))
+            .chain(self.extras.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        for (key, value) in pairs {
+            if !first {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={}", key, value)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PortalContext;
+
+    #[test]
+    fn from_args() {
+        let args: Vec<(String, String)> = url::form_urlencoded::parse(
+            b"wlanuserip=10.102.3.4&wlanacname=HTU-AC-01&wlanacip=10.101.0.1&mac=3c7c3f112233&vlan=&ssid=HTU&page=pc",
+        )
+        .into_owned()
+        .collect();
+        let context = PortalContext::from_args(&args);
+        assert_eq!(context.user_ip.as_deref(), Some("10.102.3.4"));
+        assert_eq!(context.ac_name.as_deref(), Some("HTU-AC-01"));
+        assert_eq!(context.ac_ip.as_deref(), Some("10.101.0.1"));
+        assert_eq!(context.mac.as_deref(), Some("3c7c3f112233"));
+        assert_eq!(context.vlan, None);
+        assert_eq!(context.extras.get("page").map(String::as_str), Some("pc"));
+        assert_eq!(
+            context.to_string(),
+            "ip=10.102.3.4 mac=3c7c3f112233 ac=HTU-AC-01 ac_ip=10.101.0.1 ssid=HTU page=pc"
+        );
+    }
+}
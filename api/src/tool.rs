@@ -1,3 +1,9 @@
+#[cfg(feature = "async")]
+use tokio::time::timeout;
+
+#[cfg(feature = "async")]
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
 #[cfg(feature = "blocking")]
 pub fn ping(host: &str, port: u16) -> std::io::Result<()> {
     use std::{
@@ -13,22 +19,125 @@ pub fn ping(host: &str, port: u16) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Address family a probe is restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn of(addr: &std::net::IpAddr) -> Self {
+        if addr.is_ipv4() {
+            Self::V4
+        } else {
+            Self::V6
+        }
+    }
+}
+
+impl std::fmt::Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V4 => write!(f, "IPv4"),
+            Self::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// What probing the internet over one family found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    Online,
+    Offline,
+    /// the probe host resolved to an address it can't have, a captive portal
+    /// answering DNS in its place
+    Hijacked(std::net::IpAddr),
+}
+
 #[cfg(feature = "async")]
 pub async fn ping_async(host: &str, port: u16) -> tokio::io::Result<()> {
-    use std::time::Duration;
+    ping_async_from(host, port, None).await
+}
 
-    use tokio::{
-        io::{AsyncReadExt, AsyncWriteExt},
-        net::TcpStream,
-        time::timeout,
-    };
-    let mut socket = timeout(Duration::from_secs(2), TcpStream::connect((host, port))).await??;
-    timeout(
-        Duration::from_secs(2),
-        socket.write_all(b"GET / HTTP/1.0\r\n\r\n"),
+/// [`ping_async`] from `local_address` when given, the address of the
+/// interface the probe should go out of.
+#[cfg(feature = "async")]
+pub async fn ping_async_from(
+    host: &str,
+    port: u16,
+    local_address: Option<std::net::IpAddr>,
+) -> tokio::io::Result<()> {
+    ping_async_over(
+        host,
+        port,
+        local_address.as_ref().map(Family::of),
+        local_address,
     )
-    .await??;
+    .await
+}
+
+/// [`ping_async_from`] over `family` only, instead of whatever the system
+/// prefers, so that a dual-stack machine can tell which of its families
+/// reaches the internet.
+#[cfg(feature = "async")]
+pub async fn ping_async_over(
+    host: &str,
+    port: u16,
+    family: Option<Family>,
+    local_address: Option<std::net::IpAddr>,
+) -> tokio::io::Result<()> {
+    use tokio::net::{lookup_host, TcpStream};
+
+    let Some(family) = family else {
+        let socket = timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await??;
+        return exchange(socket).await;
+    };
+    let addr = timeout(PROBE_TIMEOUT, lookup_host((host, port)))
+        .await??
+        .find(|addr| Family::of(&addr.ip()) == family)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} has no {} address", host, family),
+            )
+        })?;
+    ping_addr_async(addr, local_address).await
+}
+
+/// [`ping_async_from`] to an address resolved by the caller, e.g. through a
+/// [`crate::dns::Resolver`].
+#[cfg(feature = "async")]
+pub async fn ping_addr_async(
+    addr: std::net::SocketAddr,
+    local_address: Option<std::net::IpAddr>,
+) -> tokio::io::Result<()> {
+    use tokio::net::TcpSocket;
+
+    let family = Family::of(&addr.ip());
+    if let Some(local_address) = local_address.filter(|addr| Family::of(addr) != family) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} can't be reached from {}", family, local_address),
+        ));
+    }
+    let socket = match family {
+        Family::V4 => TcpSocket::new_v4()?,
+        Family::V6 => TcpSocket::new_v6()?,
+    };
+    if let Some(local_address) = local_address {
+        socket.bind((local_address, 0).into())?;
+    }
+    exchange(timeout(PROBE_TIMEOUT, socket.connect(addr)).await??).await
+}
+
+/// Whether whatever listens on the other end of `socket` answers a request.
+#[cfg(feature = "async")]
+async fn exchange(mut socket: tokio::net::TcpStream) -> tokio::io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    timeout(PROBE_TIMEOUT, socket.write_all(b"GET / HTTP/1.0\r\n\r\n")).await??;
     let mut buffer = [0; 1];
-    timeout(Duration::from_secs(2), socket.read_exact(&mut buffer)).await??;
+    timeout(PROBE_TIMEOUT, socket.read_exact(&mut buffer)).await??;
     Ok(())
 }
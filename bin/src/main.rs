@@ -1,4 +1,5 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use client::DaemonClient;
 use service::BIN_PATH;
 mod service;
 mod util;
@@ -18,6 +19,11 @@ struct Args {
     #[arg(long, short)]
     pub background: bool,
 
+    /// Stop a running daemon and take over(only effective in daemon mode)
+    #[cfg(feature = "daemon")]
+    #[arg(long)]
+    pub replace: bool,
+
     /// Run tui
     #[cfg(feature = "tui")]
     #[arg(short, long)]
@@ -33,6 +39,17 @@ struct Args {
     #[arg(short, long, default_value_t = 30)]
     pub frame_rate: u16,
 
+    /// Manage the daemon on another machine over https, e.g. 192.168.1.2:11451
+    #[cfg(feature = "tls")]
+    #[arg(long, value_name = "HOST:PORT")]
+    pub remote: Option<String>,
+
+    /// Certificate fingerprint the remote daemon logs on startup, also read
+    /// from HTU_NET_FINGERPRINT
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "remote")]
+    pub fingerprint: Option<String>,
+
     /// Uninstall program
     #[arg(long)]
     pub uninstall_daemon: bool,
@@ -40,6 +57,194 @@ struct Args {
     /// Install program
     #[arg(long)]
     pub install: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the daemon config file
+    #[cfg(feature = "daemon")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Show daemon status
+    Status,
+    /// Ask the daemon to login now
+    Login,
+    /// Ask the daemon to logout
+    Logout,
+}
+
+#[cfg(feature = "daemon")]
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Check the config file for errors
+    Check,
+}
+
+#[cfg(feature = "daemon")]
+async fn check_config() -> anyhow::Result<bool> {
+    use htu_net_login_daemon::config::{config_dir, Config};
+
+    let path = config_dir()
+        .ok_or(anyhow::anyhow!("empty config dir"))?
+        .join("config.json");
+    if !path.exists() {
+        println!("配置文件不存在: {}", path.display());
+        return Ok(true);
+    }
+    match Config::check(&tokio::fs::read(&path).await?) {
+        Ok(_) => {
+            println!("配置文件无误: {}", path.display());
+            Ok(true)
+        }
+        Err(issues) => {
+            eprintln!("配置文件有误: {}", path.display());
+            for issue in issues {
+                eprintln!("  {}", issue);
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Client for the daemon given by `--remote`, or the local one.
+fn daemon_client(_args: &Args) -> anyhow::Result<DaemonClient> {
+    #[cfg(feature = "tls")]
+    if let Some(remote) = &_args.remote {
+        let fingerprint = _args
+            .fingerprint
+            .clone()
+            .or_else(|| std::env::var(client::DAEMON_FINGERPRINT_ENV).ok())
+            .ok_or(anyhow::anyhow!(
+                "--remote 需要用 --fingerprint 指定守护进程的证书指纹"
+            ))?;
+        return Ok(DaemonClient::remote(remote, &fingerprint));
+    }
+    Ok(DaemonClient::discover()?)
+}
+
+async fn run_client_command(client: DaemonClient, command: &Command) -> anyhow::Result<()> {
+    match command {
+        Command::Status => {
+            let status = client.status().await?;
+            println!("版本: {}", status.version);
+            println!("账号: {}", status.account.as_deref().unwrap_or("未设定"));
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs();
+            match status.last_login {
+                Some(at) => println!("上次登录: {} 秒前", now.saturating_sub(at)),
+                None => println!("上次登录: 无"),
+            }
+            if let Some(e) = status.last_error {
+                println!("上次错误: {}", e);
+            }
+            if let Some(gateway) = status.gateway {
+                println!(
+                    "出口接口: {} ({}) {}",
+                    gateway.interface,
+                    gateway.address.as_deref().unwrap_or("无地址"),
+                    if gateway.online { "在线" } else { "离线" }
+                );
+                if let Some(e) = gateway.error {
+                    println!("出口错误: {}", e);
+                }
+            }
+            if let Some(keepalive) = status.keepalive {
+                println!(
+                    "保活间隔: {} 秒({})，断线 {} 次",
+                    keepalive.interval,
+                    if keepalive.learned {
+                        "自动调整"
+                    } else {
+                        "已配置"
+                    },
+                    keepalive.drops
+                );
+            }
+            if let Some(schedule) = status.schedule {
+                let (state, next) = if schedule.allowed {
+                    ("开放中", "暂停")
+                } else {
+                    ("已暂停", "开放")
+                };
+                match schedule.next_change {
+                    Some(at) => println!(
+                        "登录时段: {}，{} 分钟后{}",
+                        state,
+                        at.saturating_sub(now).div_ceil(60),
+                        next
+                    ),
+                    None => println!("登录时段: {}", state),
+                }
+            }
+            if let Some(session) = status.session {
+                println!(
+                    "当前会话: {} 经 {} 登录于 {} 秒前",
+                    session.account,
+                    session.portal,
+                    now.saturating_sub(session.logged_in_at)
+                );
+            }
+            if let Some(portal) = status.portal {
+                println!("接入信息: {}", portal);
+            }
+            if let Some(connectivity) = status.connectivity {
+                let state = |ok: bool| if ok { "通" } else { "不通" };
+                println!(
+                    "网络连通: IPv4 {} / IPv6 {} ({} 秒前)",
+                    state(connectivity.ipv4),
+                    state(connectivity.ipv6),
+                    now.saturating_sub(connectivity.checked_at)
+                );
+                if let Some(addr) = connectivity.hijacked {
+                    println!("DNS被劫持: 探测域名被解析到 {}，需要认证", addr);
+                }
+            }
+            for issue in &status.config_issues {
+                println!("配置问题: {}", issue);
+            }
+            if let Some(paused) = status.paused {
+                match paused.until {
+                    Some(at) => println!(
+                        "自动登录已暂停: {}，{} 分钟后重试",
+                        paused.reason,
+                        at.saturating_sub(now).div_ceil(60)
+                    ),
+                    None => println!(
+                        "自动登录已停止: {}，修改账号或执行 htu-net login 后恢复",
+                        paused.reason
+                    ),
+                }
+            }
+            if let Some(fingerprint) = status.tls_fingerprint {
+                println!("证书指纹: {}", fingerprint);
+            }
+            if let Some(crash) = status.crashes.last() {
+                println!(
+                    "子系统崩溃: {} 次，最近一次 {}: {}",
+                    status.crashes.len(),
+                    crash.subsystem,
+                    crash.reason
+                );
+            }
+        }
+        Command::Login => {
+            client.login().await?;
+            println!("登录成功");
+        }
+        Command::Logout => {
+            client.logout().await?;
+            println!("登出成功");
+        }
+        #[cfg(feature = "daemon")]
+        Command::Config { .. } => unreachable!(),
+    }
+    Ok(())
 }
 
 async fn uninstall_daemon(_: &mut Args) {
@@ -67,7 +272,6 @@ async fn install(_: &mut Args) {
                 ])
                 .status()
                 .unwrap();
-            return;
         } else {
             let _ = service::uninstall_daemon().await;
             let _ = tokio::fs::remove_file(BIN_PATH).await;
@@ -102,19 +306,63 @@ async fn install(_: &mut Args) {
 }
 
 async fn run_tui(app_args: &Args) {
-    if let Err(e) = htu_net_login_tui::run(app_args.frame_rate, app_args.tick_rate).await {
+    let client = match daemon_client(app_args) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = htu_net_login_tui::run(client, app_args.frame_rate, app_args.tick_rate).await {
         eprintln!("TUI 运行错误: {}", e)
     }
 }
 
+/// Managing a remote daemon never installs anything here.
+fn remote(_args: &Args) -> bool {
+    #[cfg(feature = "tls")]
+    return _args.remote.is_some();
+    #[cfg(not(feature = "tls"))]
+    false
+}
+
 #[tokio::main(worker_threads = 2)]
 async fn main() {
     let mut app_args = Args::parse();
 
+    match &app_args.command {
+        #[cfg(feature = "daemon")]
+        Some(Command::Config {
+            action: ConfigAction::Check,
+        }) => match check_config().await {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("配置文件检查失败: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Some(command) => {
+            let res = match daemon_client(&app_args) {
+                Ok(client) => run_client_command(client, command).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => (),
+    }
+
     // run daemon
     #[cfg(feature = "daemon")]
     if app_args.daemon {
-        service::run_service(app_args.background).await.unwrap();
+        if let Err(e) = service::run_service(app_args.background, app_args.replace).await {
+            eprintln!("守护进程启动失败: {}", e);
+            std::process::exit(1);
+        }
         return;
     };
 
@@ -129,7 +377,7 @@ async fn main() {
     };
 
     #[cfg(feature = "tui")]
-    if app_args.ui {
+    if app_args.ui || remote(&app_args) {
         run_tui(&app_args).await;
         return;
     }
@@ -4,6 +4,8 @@ mod serv;
 use flexi_logger::{Duplicate, FileSpec, Logger, LoggerHandle, WriteMode};
 use htu_net_login_daemon::config::config_dir;
 #[cfg(feature = "daemon")]
+use htu_net_login_daemon::instance::{InstanceLock, LockError};
+#[cfg(feature = "daemon")]
 pub use serv::daemon::{install_daemon, run_service, uninstall_daemon};
 #[allow(unused_imports)]
 pub use serv::{install_bin, is_admin, is_bin_exists, is_installed, BIN_PATH};
@@ -18,3 +20,35 @@ pub fn init_logger() -> anyhow::Result<LoggerHandle> {
         .write_mode(WriteMode::Direct)
         .start()?)
 }
+
+/// Time a replaced daemon gets to release its lock
+#[cfg(feature = "daemon")]
+const REPLACE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Make sure this is the only daemon, stopping the running one through its
+/// api first when `replace` is set.
+#[cfg(feature = "daemon")]
+pub async fn lock_instance(replace: bool) -> anyhow::Result<InstanceLock> {
+    use std::time::{Duration, Instant};
+
+    let dir = config_dir().ok_or(anyhow::anyhow!("empty config dir"))?;
+    match InstanceLock::acquire(&dir) {
+        Err(e @ LockError::AlreadyRunning { .. }) if !replace => {
+            anyhow::bail!("{}, use --replace to take over", e)
+        }
+        Err(LockError::AlreadyRunning { pid }) => {
+            log::info!("replacing running daemon (pid {:?})", pid);
+            client::DaemonClient::discover()?.shutdown().await?;
+            let deadline = Instant::now() + REPLACE_TIMEOUT;
+            loop {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                match InstanceLock::acquire(&dir) {
+                    Ok(lock) => return Ok(lock),
+                    Err(LockError::AlreadyRunning { .. }) if Instant::now() < deadline => (),
+                    Err(e) => anyhow::bail!("{}, it didn't stop in time", e),
+                }
+            }
+        }
+        res => Ok(res?),
+    }
+}
@@ -17,7 +17,7 @@ pub mod daemon {
 
     use winreg::enums::{HKEY_CURRENT_USER, KEY_READ, KEY_WRITE};
 
-    use crate::service::init_logger;
+    use crate::service::{init_logger, lock_instance};
 
     use super::BIN_PATH;
 
@@ -36,12 +36,9 @@ pub mod daemon {
 
     pub async fn uninstall_daemon() -> anyhow::Result<()> {
         get_regkey()?.delete_value(DAEMON_NAME)?;
-        let _ = reqwest::ClientBuilder::new()
-            .timeout(Duration::from_secs(1))
-            .build()?
-            .get("http://127.0.0.1:11451/exit")
-            .send()
-            .await;
+        if let Ok(client) = client::DaemonClient::discover() {
+            let _ = client.with_timeout(Duration::from_secs(1)).shutdown().await;
+        }
         Ok(())
     }
 
@@ -49,12 +46,17 @@ pub mod daemon {
         Ok(get_regkey()?.get_value::<String, _>(DAEMON_NAME).is_ok())
     }
 
-    pub async fn run_service(background: bool) -> anyhow::Result<()> {
+    pub async fn run_service(background: bool, replace: bool) -> anyhow::Result<()> {
         if background {
             super::free_console();
         }
         init_logger()?;
-        htu_net_login_daemon::start().await;
+        let _lock = lock_instance(replace).await.inspect_err(|e| {
+            log::error!("{}", e);
+        })?;
+        htu_net_login_daemon::start().await.inspect_err(|e| {
+            log::error!("daemon failed to start: {}", e);
+        })?;
         Ok(())
     }
 }
@@ -0,0 +1,411 @@
+//! Typed async client for the daemon http api.
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+use std::{error::Error, fmt::Display, time::Duration};
+
+use proto::{
+    path, Account, ApiError, Discovery, Envelope, ErrorCode, Event, KickRequest, LoginRecord,
+    OnlineDevice, Status, UserInfo, APP_DIR, DEFAULT_ADDR, DISCOVERY_FILE,
+};
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+
+/// Environment variable overriding the daemon address, e.g. `127.0.0.1:11451`
+pub const DAEMON_ADDR_ENV: &str = "HTU_NET_DAEMON";
+/// Environment variable holding the api token of a daemon on another machine
+pub const DAEMON_TOKEN_ENV: &str = "HTU_NET_TOKEN";
+/// Environment variable holding the certificate fingerprint of a daemon
+/// serving https
+pub const DAEMON_FINGERPRINT_ENV: &str = "HTU_NET_FINGERPRINT";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// logging in talks to the portal several times, give it more room
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// the daemon is not running or not listening on this address
+    Unreachable(reqwest::Error),
+    Timeout(reqwest::Error),
+    Request(reqwest::Error),
+    /// the daemon answered with an error
+    Daemon {
+        status: u16,
+        error: ApiError,
+    },
+    InvalidResponse(String),
+    /// a certificate fingerprint was given but can't be checked
+    Tls(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unreachable(e) => write!(f, "Daemon Unreachable: {}", e),
+            Self::Timeout(e) => write!(f, "Request Timeout: {}", e),
+            Self::Request(e) => write!(f, "Request Error: {}", e),
+            Self::Daemon { error, .. } => write!(f, "{}", error),
+            Self::InvalidResponse(body) => write!(f, "Invalid Response: {}", body),
+            Self::Tls(msg) => write!(f, "TLS Error: {}", msg),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e)
+        } else if e.is_connect() {
+            Self::Unreachable(e)
+        } else {
+            Self::Request(e)
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Non-empty value of an environment variable.
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    base: String,
+    http: Client,
+    timeout: Duration,
+    token: Option<String>,
+}
+
+impl DaemonClient {
+    /// `addr` is either `host:port` or a full `http://host:port` url, an
+    /// `https://` one needs [`Self::with_fingerprint`].
+    pub fn new(addr: &str) -> Self {
+        let addr = addr.trim_end_matches('/');
+        let base = if addr.contains("://") {
+            addr.to_string()
+        } else {
+            format!("http://{}", addr)
+        };
+        Self {
+            base,
+            http: Self::http().build().unwrap(),
+            timeout: TIMEOUT,
+            token: None,
+        }
+    }
+
+    /// Client for the daemon address found in the environment, the address
+    /// the local daemon published, or the default local address. Fails when
+    /// a fingerprint comes along that this client can't pin.
+    pub fn discover() -> Result<Self> {
+        let client = match env(DAEMON_ADDR_ENV) {
+            Some(addr) => Self::new(&addr).pinned_to(env(DAEMON_FINGERPRINT_ENV))?,
+            None => match Self::discovery() {
+                Some(discovery) => Self::new(&discovery.addr).pinned_to(discovery.fingerprint)?,
+                None => Self::new(DEFAULT_ADDR),
+            },
+        };
+        Ok(client.with_env_token())
+    }
+
+    /// Client for the daemon serving https on another machine at
+    /// `host:port`, the token is taken from the environment.
+    #[cfg(feature = "tls")]
+    pub fn remote(addr: &str, fingerprint: &str) -> Self {
+        Self::new(&format!("https://{}", addr))
+            .with_fingerprint(fingerprint)
+            .with_env_token()
+    }
+
+    fn with_env_token(self) -> Self {
+        match env(DAEMON_TOKEN_ENV) {
+            Some(token) => self.with_token(token),
+            None => self,
+        }
+    }
+
+    /// Connecting unpinned instead would trust whoever answers.
+    fn pinned_to(self, fingerprint: Option<String>) -> Result<Self> {
+        let Some(fingerprint) = fingerprint else {
+            return Ok(self);
+        };
+        if !self.base.starts_with("https://") {
+            return Err(ClientError::Tls(format!(
+                "fingerprint given for {}, which isn't https",
+                self.base
+            )));
+        }
+        #[cfg(feature = "tls")]
+        return Ok(self.with_fingerprint(&fingerprint));
+        #[cfg(not(feature = "tls"))]
+        Err(ClientError::Tls(format!(
+            "built without tls, can't pin {} to {}",
+            self.base, fingerprint
+        )))
+    }
+
+    /// Address file of the daemon running on this machine, if any.
+    pub fn discovery() -> Option<Discovery> {
+        let path = dirs::config_dir()?.join(APP_DIR).join(DISCOVERY_FILE);
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    /// Token sent as bearer auth, needed when the daemon is on another machine.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Trust only the certificate with this sha-256 fingerprint, the one a
+    /// daemon serving https logs on startup.
+    #[cfg(feature = "tls")]
+    pub fn with_fingerprint(mut self, fingerprint: &str) -> Self {
+        self.http = Self::http()
+            .use_preconfigured_tls(tls::pinned(fingerprint))
+            .build()
+            .unwrap();
+        self
+    }
+
+    /// Timeout of a single request, login excluded.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn http() -> ClientBuilder {
+        Client::builder().connect_timeout(CONNECT_TIMEOUT)
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base
+    }
+
+    /// `host:port` of the daemon, without the scheme.
+    pub fn host(&self) -> &str {
+        self.base
+            .split_once("://")
+            .map_or(self.base.as_str(), |(_, host)| host)
+    }
+
+    /// Whether the daemon is on this machine, any other address only
+    /// answers requests carrying the api token.
+    pub fn is_local(&self) -> bool {
+        let host = self.host();
+        let name = host.rsplit_once(':').map_or(host, |(name, _)| name);
+        name == "localhost"
+            || name
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback())
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    async fn send(&self, req: RequestBuilder) -> Result<Response> {
+        let resp = req.send().await?;
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let body = resp.text().await?;
+        let error = match serde_json::from_str::<Envelope<serde_json::Value>>(&body) {
+            Ok(Envelope {
+                error: Some(error), ..
+            }) => error,
+            _ => ApiError::new(ErrorCode::Unknown, body),
+        };
+        Err(ClientError::Daemon {
+            status: status.as_u16(),
+            error,
+        })
+    }
+
+    /// Data of a successful response, `None` if the daemon sent none.
+    async fn data<T: DeserializeOwned>(resp: Response) -> Result<Option<T>> {
+        let body = resp.text().await?;
+        let envelope: Envelope<T> =
+            serde_json::from_str(&body).map_err(|_| ClientError::InvalidResponse(body))?;
+        envelope.into_result().map_err(|error| ClientError::Daemon {
+            status: error.code.status(),
+            error,
+        })
+    }
+
+    async fn required<T: DeserializeOwned>(resp: Response) -> Result<T> {
+        Self::data(resp)
+            .await?
+            .ok_or_else(|| ClientError::InvalidResponse("missing data".into()))
+    }
+
+    fn authorize(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.authorize(self.http.get(self.url(path)).timeout(self.timeout))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.authorize(self.http.post(self.url(path)).timeout(self.timeout))
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.send(self.get(path::PING)).await.map(|_| ())
+    }
+
+    pub async fn get_user(&self) -> Result<Option<UserInfo>> {
+        Self::data(self.send(self.get(path::USER)).await?).await
+    }
+
+    pub async fn set_user(&self, user: &UserInfo) -> Result<()> {
+        self.send(self.post(path::USER).json(user))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn status(&self) -> Result<Status> {
+        Self::required(self.send(self.get(path::STATUS)).await?).await
+    }
+
+    /// Recent login attempts, latest first.
+    pub async fn history(&self) -> Result<Vec<LoginRecord>> {
+        Self::required(self.send(self.get(path::HISTORY)).await?).await
+    }
+
+    /// Ask the daemon to log in right now.
+    pub async fn login(&self) -> Result<()> {
+        let req = self.post(path::LOGIN).timeout(LOGIN_TIMEOUT);
+        self.send(req).await.map(|_| ())
+    }
+
+    pub async fn logout(&self) -> Result<()> {
+        self.send(self.post(path::LOGOUT)).await.map(|_| ())
+    }
+
+    /// Balance, traffic and online devices, `refresh` skips the daemon's
+    /// cache.
+    pub async fn account(&self, refresh: bool) -> Result<Account> {
+        let req = self.get(path::ACCOUNT).query(&[("refresh", refresh)]);
+        Self::required(self.send(req).await?).await
+    }
+
+    /// Sessions of the account, asked with its credentials so that it works
+    /// without being logged in.
+    pub async fn sessions(&self) -> Result<Vec<OnlineDevice>> {
+        Self::required(self.send(self.get(path::SESSIONS)).await?).await
+    }
+
+    /// Force the session with [`OnlineDevice::session`] `session` offline.
+    pub async fn kick(&self, session: &str) -> Result<()> {
+        let kick = KickRequest {
+            session: session.to_string(),
+        };
+        self.send(self.post(path::SESSIONS).json(&kick))
+            .await
+            .map(|_| ())
+    }
+
+    /// Stop the daemon, also works with daemons older than the versioned api
+    /// so that upgrades can replace them.
+    pub async fn shutdown(&self) -> Result<()> {
+        match self.send(self.post(path::SHUTDOWN)).await {
+            Err(ClientError::Daemon { status: 404, .. }) => {
+                self.send(self.get(path::LEGACY_EXIT)).await.map(|_| ())
+            }
+            res => res.map(|_| ()),
+        }
+    }
+
+    /// Receive events as they happen, the stream ends when the daemon stops.
+    pub async fn subscribe_events(&self) -> Result<EventStream> {
+        // no timeout, the response body stays open
+        let req = self.authorize(self.http.get(self.url(path::EVENTS)));
+        let resp = self.send(req).await?;
+        Ok(EventStream { resp, buf: vec![] })
+    }
+}
+
+pub struct EventStream {
+    resp: Response,
+    buf: Vec<u8>,
+}
+
+impl EventStream {
+    /// Next event, `None` once the daemon closed the stream.
+    pub async fn next(&mut self) -> Option<Result<Event>> {
+        loop {
+            if let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+                let line = self.buf.drain(..=end).collect::<Vec<_>>();
+                return Some(serde_json::from_slice(&line).map_err(|_| {
+                    ClientError::InvalidResponse(String::from_utf8_lossy(&line).into())
+                }));
+            }
+            match self.resp.chunk().await {
+                Ok(Some(chunk)) => self.buf.extend_from_slice(&chunk),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DaemonClient;
+
+    #[test]
+    fn base_url() {
+        assert_eq!(
+            DaemonClient::new("127.0.0.1:11451").base_url(),
+            "http://127.0.0.1:11451"
+        );
+        assert_eq!(
+            DaemonClient::new("https://pi.local:8443/").base_url(),
+            "https://pi.local:8443"
+        );
+    }
+
+    #[test]
+    fn fingerprint_needs_https() {
+        let fingerprint = Some("AB:CD".to_string());
+        assert!(DaemonClient::new("192.168.1.2:11451")
+            .pinned_to(fingerprint.clone())
+            .is_err());
+        assert!(DaemonClient::new("192.168.1.2:11451")
+            .pinned_to(None)
+            .is_ok());
+        #[cfg(not(feature = "tls"))]
+        assert!(DaemonClient::new("https://192.168.1.2:11451")
+            .pinned_to(fingerprint)
+            .is_err());
+    }
+
+    #[test]
+    fn local() {
+        assert!(DaemonClient::new("127.0.0.1:11451").is_local());
+        assert!(DaemonClient::new("http://[::1]:11451").is_local());
+        assert!(DaemonClient::new("localhost:11451").is_local());
+        let lan = DaemonClient::new("192.168.1.2:11451");
+        assert!(!lan.is_local());
+        assert_eq!(lan.host(), "192.168.1.2:11451");
+        assert!(!DaemonClient::new("https://pi.local:8443").is_local());
+    }
+}
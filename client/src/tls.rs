@@ -0,0 +1,93 @@
+//! Pins the self-signed certificate of a daemon serving the api over https.
+
+use std::{sync::Arc, time::SystemTime};
+
+use ring::digest::{digest, SHA256};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, Error, ServerName,
+};
+
+/// Accepts exactly the certificate with the pinned fingerprint, whatever
+/// name it was issued for.
+struct Pinned {
+    /// uppercase hex without separators
+    fingerprint: String,
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let fingerprint = fingerprint(&end_entity.0);
+        if normalize(&fingerprint) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::General(format!(
+                "certificate fingerprint {} doesn't match the pinned one",
+                fingerprint
+            )))
+        }
+    }
+}
+
+/// Tls config trusting only the certificate with `fingerprint`, given in
+/// any case with or without colons.
+pub(crate) fn pinned(fingerprint: &str) -> ClientConfig {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(Pinned {
+            fingerprint: normalize(fingerprint),
+        }))
+        .with_no_client_auth()
+}
+
+/// Sha-256 fingerprint of a DER encoded certificate, as the daemon prints it.
+pub fn fingerprint(cert: &[u8]) -> String {
+    proto::format_fingerprint(digest(&SHA256, cert).as_ref())
+}
+
+fn normalize(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use rustls::{client::ServerCertVerifier, Certificate, ServerName};
+
+    use super::{fingerprint, normalize, Pinned};
+
+    #[test]
+    fn pinned_fingerprint() {
+        let cert = Certificate(b"not really a certificate".to_vec());
+        let verify = |pin: &str| {
+            Pinned {
+                fingerprint: normalize(pin),
+            }
+            .verify_server_cert(
+                &cert,
+                &[],
+                &ServerName::try_from("pi.local").unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+        };
+        let pin = fingerprint(&cert.0);
+        assert!(verify(&pin).is_ok());
+        assert!(verify(&pin.replace(':', "").to_lowercase()).is_ok());
+        assert!(verify(&fingerprint(b"another certificate")).is_err());
+    }
+}
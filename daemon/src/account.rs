@@ -0,0 +1,234 @@
+//! Keeps what the portal tells about the account, warns once the balance or
+//! traffic starts running low, and forces sessions of the account offline.
+
+use std::{fmt::Display, time::Duration};
+
+use api::{
+    account::{account_info_with, kick_with, online_sessions_with, AccountError, AccountInfo},
+    auth::UserInfo,
+};
+use proto::OnlineDevice;
+use proto::{Account, Event};
+use tokio::{sync::broadcast::error::RecvError, time};
+
+use crate::{
+    config::{unix_now, AppConfig, AppState, GlobalAppInfo},
+    daemon, gateway,
+};
+
+/// seconds cached details are served for
+const MAX_AGE: u64 = 300;
+/// how often to look at the account while logged in
+const WATCH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Something running low, in fen and MiB.
+#[derive(Debug, PartialEq, Eq)]
+enum Low {
+    Balance(i64),
+    Quota(u64),
+}
+
+impl Low {
+    fn same_kind(&self, other: &Low) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl Display for Low {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Balance(fen) => {
+                let sign = if *fen < 0 { "-" } else { "" };
+                let fen = fen.unsigned_abs();
+                write!(
+                    f,
+                    "余额不足: 剩余 {}{}.{:02} 元",
+                    sign,
+                    fen / 100,
+                    fen % 100
+                )
+            }
+            Self::Quota(mb) => write!(f, "流量不足: 剩余 {} MB", mb),
+        }
+    }
+}
+
+/// What runs low in `info`, a threshold of 0 turns its warning off.
+fn lows(info: &AccountInfo, low_balance: i64, low_quota_mb: u64) -> Vec<Low> {
+    let mut lows = vec![];
+    if let Some(balance) = info
+        .balance
+        .filter(|balance| low_balance > 0 && *balance < low_balance)
+    {
+        lows.push(Low::Balance(balance));
+    }
+    if let Some(remaining) = info.remaining_mb().filter(|mb| *mb < low_quota_mb) {
+        lows.push(Low::Quota(remaining));
+    }
+    lows
+}
+
+/// The account details, from the cache unless they are stale or `refresh`
+/// is set.
+pub(crate) async fn get(appinfo: &GlobalAppInfo, refresh: bool) -> Result<Account, AccountError> {
+    let cached = appinfo.read().await.account();
+    if let Some(account) =
+        cached.filter(|a| !refresh && unix_now().saturating_sub(a.fetched_at) < MAX_AGE)
+    {
+        return Ok(account);
+    }
+    let base = daemon::logout_url_base(appinfo).await;
+    let network = gateway::network(appinfo).await;
+    let info = account_info_with(network.client(), &base).await?;
+    let (low_balance, low_quota_mb) = {
+        let appinfo = appinfo.read().await;
+        let conf = appinfo.config();
+        (conf.low_balance(), conf.low_quota_mb())
+    };
+    let now_low = lows(&info, low_balance, low_quota_mb);
+    let account = Account {
+        warnings: now_low.iter().map(Low::to_string).collect(),
+        info,
+        fetched_at: unix_now(),
+    };
+    let mut appinfo_write = appinfo.write().await;
+    let was_low = appinfo_write
+        .set_account(account.clone())
+        .map(|prev| lows(&prev.info, low_balance, low_quota_mb))
+        .unwrap_or_default();
+    let newly_low: Vec<_> = now_low
+        .iter()
+        .filter(|low| !was_low.iter().any(|was| was.same_kind(low)))
+        .map(Low::to_string)
+        .collect();
+    if newly_low.is_empty() {
+        return Ok(account);
+    }
+    appinfo_write.emit(Event::AccountLow {
+        warnings: newly_low.clone(),
+    });
+    drop(appinfo_write);
+    log::warn!("account running low: {:?}", now_low);
+    #[cfg(feature = "sys-notify")]
+    daemon::notify(&newly_low.join("，")).await;
+    Ok(account)
+}
+
+/// Sessions of `user`, asked with its credentials so that it works while
+/// the device limit keeps us from logging in.
+pub(crate) async fn sessions(
+    appinfo: &GlobalAppInfo,
+    user: &UserInfo,
+) -> Result<Vec<OnlineDevice>, AccountError> {
+    let base = daemon::logout_url_base(appinfo).await;
+    let network = gateway::network(appinfo).await;
+    online_sessions_with(network.client(), &base, user).await
+}
+
+/// Force the session `session` of `user` offline, `ip` is what it is known
+/// by in logs and events.
+pub(crate) async fn kick(
+    appinfo: &GlobalAppInfo,
+    user: &UserInfo,
+    session: &str,
+    ip: &str,
+    automatic: bool,
+) -> Result<(), AccountError> {
+    let base = daemon::logout_url_base(appinfo).await;
+    let network = gateway::network(appinfo).await;
+    kick_with(network.client(), &base, user, session).await?;
+    log::info!("kicked session {} of {} offline", session, ip);
+    let mut appinfo_write = appinfo.write().await;
+    // keep the cached device list honest until the next fetch
+    if let Some(mut account) = appinfo_write.account() {
+        account
+            .info
+            .devices
+            .retain(|device| device.session.as_deref() != Some(session));
+        appinfo_write.set_account(account);
+    }
+    appinfo_write.emit(Event::SessionKicked {
+        ip: ip.to_string(),
+        automatic,
+    });
+    Ok(())
+}
+
+/// Make room for a login by kicking the oldest session of `user`, false
+/// when there was none to kick or it didn't work.
+pub(crate) async fn kick_oldest(appinfo: &GlobalAppInfo, user: &UserInfo) -> bool {
+    let sessions = match sessions(appinfo, user).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            log::warn!("failed to list sessions to kick: {}", e);
+            return false;
+        }
+    };
+    let Some(oldest) = api::account::oldest(&sessions) else {
+        log::warn!("device limit reached, but no session can be kicked");
+        return false;
+    };
+    let session = oldest.session.as_deref().unwrap_or_default();
+    if let Err(e) = kick(appinfo, user, session, &oldest.ip, true).await {
+        log::warn!("failed to kick the oldest session: {}", e);
+        return false;
+    }
+    #[cfg(feature = "sys-notify")]
+    daemon::notify(&format!("在线设备已达上限，已下线 {}", oldest.ip)).await;
+    true
+}
+
+/// Look at the account after every login and now and then while logged in,
+/// so that low balance is noticed without anyone asking.
+pub async fn watch(appinfo: GlobalAppInfo) {
+    let shutdown = appinfo.read().await.shutdown_token();
+    let mut events = appinfo.read().await.subscribe();
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            event = events.recv() => match event {
+                Ok(Event::LoginSucceeded { .. }) => (),
+                Err(RecvError::Closed) => break,
+                _ => continue,
+            },
+            _ = time::sleep(WATCH_INTERVAL) => {
+                if appinfo.read().await.status().last_login.is_none() {
+                    continue;
+                }
+            }
+        }
+        if let Err(e) = get(&appinfo, true).await {
+            // not every portal has the self-service api
+            log::debug!("failed to fetch account details: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::account::AccountInfo;
+
+    use super::{lows, Low};
+
+    #[test]
+    fn warns_when_low() {
+        let info = AccountInfo {
+            balance: Some(320),
+            used_mb: Some(50500),
+            quota_mb: Some(51200),
+            ..Default::default()
+        };
+        let found = lows(&info, 500, 1024);
+        assert_eq!(found, [Low::Balance(320), Low::Quota(700)]);
+        assert_eq!(found[0].to_string(), "余额不足: 剩余 3.20 元");
+        assert!(lows(&info, 0, 0).is_empty());
+        let arrears = AccountInfo {
+            balance: Some(-5),
+            ..Default::default()
+        };
+        assert_eq!(
+            lows(&arrears, 500, 0)[0].to_string(),
+            "余额不足: 剩余 -0.05 元"
+        );
+    }
+}
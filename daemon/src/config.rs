@@ -1,31 +1,247 @@
-use std::{ops::Deref, path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    ops::Deref,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use api::auth::UserInfo;
+use api::{
+    auth::{AuthError, Session, UserInfo},
+    dns::Resolver,
+    portal::PortalContext,
+};
+use chrono::{Local, NaiveTime, Weekday};
+use proto::{
+    Account, Connectivity, Crash, Event, GatewayStatus, LoginPause, LoginRecord, Status, APP_DIR,
+    DEFAULT_ADDR,
+};
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs::{self, File},
-    io,
-    sync::RwLock,
+    io::{self, AsyncWriteExt},
+    sync::{broadcast, Mutex, RwLock},
 };
+use tokio_util::sync::CancellationToken;
 
-use crate::Error;
+use crate::{
+    keepalive::KeepAlive,
+    metrics::METRICS,
+    schedule,
+    validate::{self, ConfigIssue},
+    Error,
+};
+
+const DEFAULT_RETRY_INTERVAL: u64 = 5;
+const EVENT_BUFFER: usize = 32;
+const HISTORY_LEN: usize = 50;
+const CRASHES_LEN: usize = 10;
+/// loopback, the LAN address has to be configured so that the page isn't
+/// served on the WAN as well
+const DEFAULT_STATUS_ADDR: &str = "127.0.0.1:8080";
+const DEFAULT_LOW_BALANCE: u64 = 5;
+const DEFAULT_LOW_QUOTA_MB: u64 = 1024;
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
     user: Option<UserInfo>,
     last_login_url: Option<String>,
     logout_url_base: Option<String>,
+    /// what the last login left behind, none once logged out
+    session: Option<Session>,
+    /// seconds to wait before retrying a failed login
+    retry_interval: Option<u64>,
+    /// seconds between keep-alive probes, learned from session drops when
+    /// unset
+    keepalive_interval: Option<u64>,
+    /// log out of the campus network when the daemon stops
+    logout_on_shutdown: Option<bool>,
+    /// warn once the balance drops below this many yuan
+    low_balance: Option<u64>,
+    /// warn once less than this many MiB of traffic are left
+    low_quota_mb: Option<u64>,
+    /// force the oldest other session offline when the portal refuses a
+    /// login for too many devices
+    kick_oldest_on_limit: Option<bool>,
+    /// address of the api, port 0 picks a free one, changes apply after a
+    /// restart
+    listen: Option<String>,
+    /// bearer token required from clients that aren't on this machine
+    api_token: Option<String>,
+    /// serve the api over https with a self-signed certificate, changes
+    /// apply after a restart
+    tls: Option<bool>,
+    /// log in on behalf of a LAN this machine is the gateway of
+    gateway: Option<GatewayConfig>,
+    /// only log in automatically at certain times
+    schedule: Option<ScheduleConfig>,
+    /// resolve names without the system's DNS, which the portal hijacks
+    dns: Option<DnsConfig>,
+    /// auto-login held back after the portal refused, written by the daemon
+    login_hold: Option<LoginHold>,
+}
+
+/// Auto-login held back for an account after the portal refused it, kept in
+/// the config so that a restart doesn't retry refused credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginHold {
+    /// account the portal refused, the hold is lifted once the account or
+    /// its password changes
+    pub user_id: String,
+    /// what the portal refused for
+    pub reason: String,
+    /// unix timestamp to resume at, none to wait for the account to change
+    pub until: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayConfig {
+    /// interface facing the campus network, e.g. `eth0`
+    wan_interface: String,
+    /// address of the status page for LAN clients, e.g. the LAN address of
+    /// this machine, changes apply after a restart
+    status_listen: Option<String>,
+}
+
+impl GatewayConfig {
+    pub fn wan_interface(&self) -> &str {
+        &self.wan_interface
+    }
+
+    /// Where to serve the status page, never every interface, which would
+    /// include the WAN.
+    pub fn status_addr(&self) -> SocketAddr {
+        self.status_listen
+            .as_deref()
+            .and_then(|addr| addr.parse::<SocketAddr>().ok())
+            .filter(|addr| !addr.ip().is_unspecified())
+            .unwrap_or_else(|| DEFAULT_STATUS_ADDR.parse().unwrap())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsConfig {
+    /// asked in order instead of the system's resolver, e.g. `223.5.5.5` or
+    /// `[2400:3200::1]:53`
+    servers: Option<Vec<String>>,
+    /// names resolved without asking anyone, e.g. the portal's
+    hosts: Option<BTreeMap<String, Vec<String>>>,
+}
+
+impl DnsConfig {
+    pub fn resolver(&self) -> Resolver {
+        let servers = self.servers.iter().flatten();
+        let mut resolver =
+            Resolver::default().with_servers(servers.filter_map(|s| Resolver::parse_server(s)));
+        for (host, addrs) in self.hosts.iter().flatten() {
+            resolver =
+                resolver.with_host(host, addrs.iter().filter_map(|a| a.parse().ok()).collect());
+        }
+        resolver
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleConfig {
+    /// local times auto-login is allowed at, any time when empty
+    windows: Vec<Window>,
+    /// log out of the campus network when a window ends
+    logout_at_end: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    /// `mon` to `sun`, every day when unset
+    days: Option<Vec<String>>,
+    /// `HH:MM`
+    start: String,
+    /// `HH:MM`, at or before `start` for windows past midnight
+    end: String,
+}
+
+impl ScheduleConfig {
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    pub fn logout_at_end(&self) -> bool {
+        self.logout_at_end.unwrap_or(false)
+    }
+}
+
+impl Window {
+    /// Whether the window opens on `day`.
+    pub fn opens_on(&self, day: Weekday) -> bool {
+        self.days
+            .as_ref()
+            .is_none_or(|days| days.iter().any(|d| d.parse::<Weekday>().ok() == Some(day)))
+    }
+
+    pub fn start(&self) -> Option<NaiveTime> {
+        parse_time(&self.start)
+    }
+
+    pub fn end(&self) -> Option<NaiveTime> {
+        parse_time(&self.end)
+    }
+}
+
+pub(crate) fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").ok()
 }
 
 impl Config {
+    /// Parse a config file, reporting every problem found instead of
+    /// stopping at the first one.
+    pub fn check(data: &[u8]) -> Result<Self, Vec<ConfigIssue>> {
+        let data: &[u8] = if data.is_empty() { b"{}" } else { data };
+        let value = validate::check_source(data, |v, value| v.config(value))?;
+        serde_json::from_value(value).map_err(|e| vec![validate::syntax_issue(&e)])
+    }
+
+    /// Parse a config file like [`Config::check`], but keep a config with
+    /// issues as long as it can be read at all, returning the issues next to
+    /// it. Used at startup, where refusing a config older versions accepted
+    /// would leave the machine offline.
+    pub fn load(data: &[u8]) -> Result<(Self, Vec<ConfigIssue>), Vec<ConfigIssue>> {
+        let data: &[u8] = if data.is_empty() { b"{}" } else { data };
+        let (value, issues) = validate::inspect_source(data, |v, value| v.config(value))?;
+        match serde_json::from_value(value) {
+            Ok(conf) => Ok((conf, issues)),
+            Err(e) => Err([validate::syntax_issue(&e)]
+                .into_iter()
+                .chain(issues)
+                .collect()),
+        }
+    }
+
+    /// Validate a json encoded [`UserInfo`], e.g. a request body.
+    pub fn check_user(data: &[u8]) -> Result<UserInfo, Vec<ConfigIssue>> {
+        let value = validate::check_source(data, |v, value| v.user(value))?;
+        serde_json::from_value(value).map_err(|e| vec![validate::syntax_issue(&e)])
+    }
+
     pub fn user(&self) -> Option<&UserInfo> {
         self.user.as_ref()
     }
 
+    /// Replace the account, lifting a hold on the old credentials.
     pub fn set_user(&mut self, user: UserInfo) {
+        if self.user.as_ref() != Some(&user) {
+            self.login_hold = None;
+        }
         self.user = Some(user);
     }
 
+    pub fn login_hold(&self) -> Option<&LoginHold> {
+        self.login_hold.as_ref()
+    }
+
+    pub fn set_login_hold(&mut self, hold: Option<LoginHold>) {
+        self.login_hold = hold;
+    }
+
     pub fn set_last_url(&mut self, url: String) {
         self.last_login_url = Some(url);
     }
@@ -34,17 +250,143 @@ impl Config {
         self.last_login_url.as_deref()
     }
 
-    pub fn set_logout_url_base(&mut self, url: String) {
-        self.logout_url_base = Some(url);
+    pub fn set_logout_url_base(&mut self, url: Option<String>) {
+        self.logout_url_base = url;
     }
 
     pub fn logout_url_base(&self) -> Option<&str> {
         self.logout_url_base.as_deref()
     }
+
+    pub fn set_session(&mut self, session: Option<Session>) {
+        self.session = session;
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.retry_interval.unwrap_or(DEFAULT_RETRY_INTERVAL))
+    }
+
+    /// Keep-alive interval set by the user, if any.
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval.map(Duration::from_secs)
+    }
+
+    /// Balance to warn below, in fen.
+    pub fn low_balance(&self) -> i64 {
+        self.low_balance.unwrap_or(DEFAULT_LOW_BALANCE) as i64 * 100
+    }
+
+    pub fn low_quota_mb(&self) -> u64 {
+        self.low_quota_mb.unwrap_or(DEFAULT_LOW_QUOTA_MB)
+    }
+
+    pub fn kick_oldest_on_limit(&self) -> bool {
+        self.kick_oldest_on_limit.unwrap_or(false)
+    }
+
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.listen
+            .as_deref()
+            .and_then(|addr| addr.parse().ok())
+            .unwrap_or_else(|| DEFAULT_ADDR.parse().unwrap())
+    }
+
+    pub fn api_token(&self) -> Option<&str> {
+        self.api_token.as_deref()
+    }
+
+    pub fn gateway(&self) -> Option<&GatewayConfig> {
+        self.gateway.as_ref()
+    }
+
+    pub fn schedule(&self) -> Option<&ScheduleConfig> {
+        self.schedule.as_ref()
+    }
+
+    /// Resolver for the portal and the probes, the system's unless
+    /// configured.
+    pub fn resolver(&self) -> Resolver {
+        self.dns
+            .as_ref()
+            .map(DnsConfig::resolver)
+            .unwrap_or_default()
+    }
+
+    pub fn tls(&self) -> bool {
+        self.tls.unwrap_or(false)
+    }
+
+    pub fn logout_on_shutdown(&self) -> bool {
+        self.logout_on_shutdown.unwrap_or(false)
+    }
+
+    /// Names of the fields that differ between `self` and `other`.
+    pub fn changes(&self, other: &Config) -> Vec<&'static str> {
+        let mut changes = vec![];
+        if self.user != other.user {
+            changes.push("user");
+        }
+        if self.last_login_url != other.last_login_url {
+            changes.push("last_login_url");
+        }
+        if self.logout_url_base != other.logout_url_base {
+            changes.push("logout_url_base");
+        }
+        if self.session != other.session {
+            changes.push("session");
+        }
+        if self.retry_interval != other.retry_interval {
+            changes.push("retry_interval");
+        }
+        if self.keepalive_interval != other.keepalive_interval {
+            changes.push("keepalive_interval");
+        }
+        if self.logout_on_shutdown != other.logout_on_shutdown {
+            changes.push("logout_on_shutdown");
+        }
+        if self.low_balance != other.low_balance {
+            changes.push("low_balance");
+        }
+        if self.low_quota_mb != other.low_quota_mb {
+            changes.push("low_quota_mb");
+        }
+        if self.kick_oldest_on_limit != other.kick_oldest_on_limit {
+            changes.push("kick_oldest_on_limit");
+        }
+        if self.listen != other.listen {
+            changes.push("listen");
+        }
+        if self.api_token != other.api_token {
+            changes.push("api_token");
+        }
+        if self.tls != other.tls {
+            changes.push("tls");
+        }
+        if self.gateway != other.gateway {
+            changes.push("gateway");
+        }
+        if self.schedule != other.schedule {
+            changes.push("schedule");
+        }
+        if self.dns != other.dns {
+            changes.push("dns");
+        }
+        if self.login_hold != other.login_hold {
+            changes.push("login_hold");
+        }
+        changes
+    }
 }
 
+#[cfg(feature = "auto-update")]
+const RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
 pub fn config_dir() -> Option<PathBuf> {
-    dirs::config_dir().map(|r| r.join("htu-net"))
+    dirs::config_dir().map(|r| r.join(APP_DIR))
 }
 
 pub(crate) trait AppConfig: Sized {
@@ -69,40 +411,92 @@ pub(crate) trait AppConfig: Sized {
         Ok((path, false))
     }
 
-    async fn load_or_create() -> Result<Self, Error> {
+    /// Load the config along with the issues it has, failing only when it
+    /// can't be read at all.
+    async fn load_or_create() -> Result<(Self, Vec<ConfigIssue>), Error> {
         let (path, created) = Self::get_or_create_path().await.map_err(Error::TokioIo)?;
         if created {
-            Ok(Self::new(Config::default(), path))
-        } else {
-            Ok(Self::new(
-                serde_json::from_slice(
-                    &fs::read(&path)
-                        .await
-                        .map(|data| if data.is_empty() { "{}".into() } else { data })
-                        .map_err(Error::TokioIo)?,
-                )
-                .map_err(Error::SerdeJson)?,
-                path,
-            ))
+            return Ok((Self::new(Config::default(), path), vec![]));
         }
+        let data = fs::read(&path).await.map_err(Error::TokioIo)?;
+        let (conf, issues) = Config::load(&data).map_err(Error::InvalidConfig)?;
+        Ok((Self::new(conf, path), issues))
     }
 
+    /// Write the config to a temp file next to it and rename it over the
+    /// original, so the watcher and editors never see a half written file.
     async fn save(&self) -> io::Result<()> {
         let (path, _) = Self::get_or_create_path().await?;
-        fs::write(path, serde_json::to_vec_pretty(self.config())?).await
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = File::create(&tmp_path).await?;
+        file.write_all(&serde_json::to_vec_pretty(self.config())?)
+            .await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(tmp_path, path).await
     }
 }
 
 pub(crate) trait AppState {
     fn running(&self) -> bool;
     fn stop(&mut self);
+    /// Cancelled once the daemon starts shutting down.
+    fn shutdown_token(&self) -> CancellationToken;
+    /// Held while logging in, so that only one login talks to the portal.
+    fn login_lock(&self) -> Arc<Mutex<()>>;
+    fn login_succeeded(&mut self);
+    fn login_failed(&mut self, e: &AuthError);
+    /// Hold auto-login back, until a login succeeds at the latest.
+    fn pause_login(&mut self, hold: LoginHold);
+    /// What the portal redirect said about this machine.
+    fn set_portal(&mut self, context: PortalContext);
+    /// Lift the hold, e.g. because the user asked to retry.
+    fn resume_login(&mut self);
+    /// Why auto-login is held back, none once the hold ran out or the
+    /// account it was for got replaced.
+    fn login_pause(&self) -> Option<LoginPause>;
+    fn status(&self) -> Status;
+    fn history(&self) -> Vec<LoginRecord>;
+    fn subsystem_crashed(&mut self, subsystem: &str, reason: String);
+    /// Fingerprint of the certificate the api is served with, if any.
+    fn set_tls_fingerprint(&mut self, fingerprint: Option<String>);
+    /// Result of the latest look at the WAN in gateway mode, returns the
+    /// previous one.
+    fn set_gateway(&mut self, status: GatewayStatus) -> Option<GatewayStatus>;
+    fn keepalive_interval(&self) -> Duration;
+    fn keepalive_probed(&mut self, ok: bool);
+    /// The session dropped between keep-alive probes.
+    fn keepalive_dropped(&mut self);
+    fn set_connectivity(&mut self, connectivity: Connectivity);
+    /// Problems of the config the daemon runs on anyway.
+    fn set_config_issues(&mut self, issues: Vec<ConfigIssue>);
+    fn logged_out(&mut self);
+    /// Account details fetched last, if any.
+    fn account(&self) -> Option<Account>;
+    /// Cache freshly fetched account details, returns the previous ones.
+    fn set_account(&mut self, account: Account) -> Option<Account>;
+    fn emit(&self, event: Event);
+    fn subscribe(&self) -> broadcast::Receiver<Event>;
 }
 
 #[derive(Debug, Clone)]
 pub struct AppInfo {
     config: Config,
     path: PathBuf,
-    running: bool,
+    shutdown: CancellationToken,
+    login_lock: Arc<Mutex<()>>,
+    last_login: Option<u64>,
+    last_error: Option<String>,
+    history: VecDeque<LoginRecord>,
+    crashes: VecDeque<Crash>,
+    tls_fingerprint: Option<String>,
+    gateway: Option<GatewayStatus>,
+    keepalive: KeepAlive,
+    account: Option<Account>,
+    portal: Option<PortalContext>,
+    connectivity: Option<Connectivity>,
+    config_issues: Vec<ConfigIssue>,
+    events: broadcast::Sender<Event>,
 }
 
 impl AppConfig for AppInfo {
@@ -110,7 +504,20 @@ impl AppConfig for AppInfo {
         Self {
             config: conf,
             path,
-            running: true,
+            shutdown: CancellationToken::new(),
+            login_lock: Arc::default(),
+            last_login: None,
+            last_error: None,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            crashes: VecDeque::with_capacity(CRASHES_LEN),
+            tls_fingerprint: None,
+            gateway: None,
+            keepalive: KeepAlive::default(),
+            account: None,
+            portal: None,
+            connectivity: None,
+            config_issues: vec![],
+            events: broadcast::channel(EVENT_BUFFER).0,
         }
     }
 
@@ -133,15 +540,197 @@ impl AppConfig for AppInfo {
 
 impl AppState for AppInfo {
     fn running(&self) -> bool {
-        self.running
+        !self.shutdown.is_cancelled()
     }
 
     fn stop(&mut self) {
-        self.running = false;
+        self.shutdown.cancel();
+    }
+
+    fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    fn login_lock(&self) -> Arc<Mutex<()>> {
+        self.login_lock.clone()
+    }
+
+    fn login_succeeded(&mut self) {
+        METRICS.login_succeeded();
+        let at = unix_now();
+        self.last_login = Some(at);
+        self.last_error = None;
+        self.config.set_login_hold(None);
+        self.record(LoginRecord {
+            at,
+            ok: true,
+            msg: None,
+        });
+        self.emit(Event::LoginSucceeded { at });
+    }
+
+    fn login_failed(&mut self, e: &AuthError) {
+        METRICS.login_failed(e);
+        let msg = e.to_string();
+        self.last_error = Some(msg.clone());
+        self.record(LoginRecord {
+            at: unix_now(),
+            ok: false,
+            msg: Some(msg.clone()),
+        });
+        self.emit(Event::LoginFailed { msg });
+    }
+
+    fn pause_login(&mut self, hold: LoginHold) {
+        self.config.set_login_hold(Some(hold));
+    }
+
+    fn set_portal(&mut self, context: PortalContext) {
+        self.portal = Some(context);
+    }
+
+    fn resume_login(&mut self) {
+        self.config.set_login_hold(None);
+    }
+
+    fn login_pause(&self) -> Option<LoginPause> {
+        let hold = self.config.login_hold()?;
+        if self.config.user().map(UserInfo::id) != Some(hold.user_id.as_str())
+            || hold.until.is_some_and(|until| until <= unix_now())
+        {
+            return None;
+        }
+        Some(LoginPause {
+            reason: hold.reason.clone(),
+            until: hold.until,
+        })
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            account: self.config.user().map(|u| u.id().to_string()),
+            last_login: self.last_login,
+            last_error: self.last_error.clone(),
+            crashes: self.crashes.iter().cloned().collect(),
+            tls_fingerprint: self.tls_fingerprint.clone(),
+            // stale once gateway mode was turned off
+            gateway: self.config.gateway().and(self.gateway.clone()),
+            keepalive: Some(self.keepalive.status(self.config.keepalive_interval())),
+            schedule: self
+                .config
+                .schedule()
+                .and_then(|conf| schedule::status(conf, Local::now())),
+            paused: self.login_pause(),
+            session: self.config.session().cloned(),
+            portal: self.portal.clone(),
+            connectivity: self.connectivity.clone(),
+            config_issues: self.config_issues.clone(),
+        }
+    }
+
+    fn history(&self) -> Vec<LoginRecord> {
+        self.history.iter().rev().cloned().collect()
+    }
+
+    fn subsystem_crashed(&mut self, subsystem: &str, reason: String) {
+        if self.crashes.len() == CRASHES_LEN {
+            self.crashes.pop_front();
+        }
+        self.crashes.push_back(Crash {
+            subsystem: subsystem.to_string(),
+            at: unix_now(),
+            reason,
+        });
+    }
+
+    fn set_tls_fingerprint(&mut self, fingerprint: Option<String>) {
+        self.tls_fingerprint = fingerprint;
+    }
+
+    fn set_gateway(&mut self, status: GatewayStatus) -> Option<GatewayStatus> {
+        self.gateway.replace(status)
+    }
+
+    fn keepalive_interval(&self) -> Duration {
+        self.keepalive.interval(self.config.keepalive_interval())
+    }
+
+    fn keepalive_probed(&mut self, ok: bool) {
+        let learn = self.config.keepalive_interval().is_none();
+        self.keepalive.probed(ok, learn);
+    }
+
+    fn keepalive_dropped(&mut self) {
+        let learn = self.config.keepalive_interval().is_none();
+        self.keepalive.dropped(learn);
     }
+
+    fn set_connectivity(&mut self, connectivity: Connectivity) {
+        let prev = self.connectivity.as_ref();
+        if prev.map(|c| (c.ipv4, c.ipv6)) != Some((connectivity.ipv4, connectivity.ipv6)) {
+            log::info!(
+                "connectivity: ipv4 {}, ipv6 {}",
+                connectivity.ipv4,
+                connectivity.ipv6
+            );
+        }
+        if let Some(addr) = connectivity
+            .hijacked
+            .as_ref()
+            .filter(|addr| prev.and_then(|c| c.hijacked.as_ref()) != Some(*addr))
+        {
+            log::warn!(
+                "dns hijacked: {} resolved to {}, the portal wants a login",
+                api::auth::PROBE_HOST,
+                addr
+            );
+        }
+        self.connectivity = Some(connectivity);
+    }
+
+    fn set_config_issues(&mut self, issues: Vec<ConfigIssue>) {
+        self.config_issues = issues;
+    }
+
+    fn logged_out(&mut self) {
+        self.keepalive.logged_out();
+        self.emit(Event::LoggedOut);
+    }
+
+    fn account(&self) -> Option<Account> {
+        self.account.clone()
+    }
+
+    fn set_account(&mut self, account: Account) -> Option<Account> {
+        self.account.replace(account)
+    }
+
+    fn emit(&self, event: Event) {
+        // no subscribers is not an error
+        let _ = self.events.send(event);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
 
 impl AppInfo {
+    fn record(&mut self, record: LoginRecord) {
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(record);
+    }
+
     pub fn global(self) -> GlobalAppInfo {
         GlobalAppInfo(Arc::new(RwLock::new(self)))
     }
@@ -160,62 +749,151 @@ impl Deref for GlobalAppInfo {
 
 impl GlobalAppInfo {
     #[cfg(feature = "auto-update")]
-    pub async fn run_auto_update(
-        &self,
-    ) -> Result<(notify::RecommendedWatcher, tokio::task::JoinHandle<()>), notify::Error> {
-        use notify::{event::AccessMode, Event, Watcher};
-        use tokio::sync::mpsc;
+    /// Reload the config whenever the file changes, until shutdown.
+    pub async fn run_auto_update(&self) -> Result<(), notify::Error> {
+        use notify::{event::AccessMode, EventKind, Watcher};
+        use tokio::{sync::mpsc, time};
 
-        let app_info = self.clone();
         let (tx, mut rx) = mpsc::unbounded_channel::<()>();
-        let conf_path = app_info.read().await.config_path().clone();
-        let mut watcher = notify::recommended_watcher(move |res: Result<Event, _>| match res {
-            Ok(r) => {
-                if let notify::EventKind::Access(notify::event::AccessKind::Close(
-                    AccessMode::Write,
-                )) = r.kind
-                {
-                    if r.paths.iter().any(|p| *p == conf_path) {
-                        log::info!("config file updated");
-                        tx.send(()).unwrap();
+        let conf_path = self.read().await.config_path().clone();
+        let watched_path = conf_path.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |res: Result<notify::Event, _>| match res {
+                Ok(r) => {
+                    // editors and our own atomic save replace the file instead of
+                    // writing it in place, so renames and creates count as well
+                    let relevant = matches!(
+                        r.kind,
+                        EventKind::Create(_)
+                            | EventKind::Modify(_)
+                            | EventKind::Access(notify::event::AccessKind::Close(
+                                AccessMode::Write
+                            ))
+                    );
+                    if relevant && r.paths.contains(&watched_path) {
+                        let _ = tx.send(());
                     }
                 }
-            }
-            Err(e) => log::error!("watch err:{:?}", e),
-        })?;
-        let app_info_inner = app_info.clone();
-        let handle = tokio::spawn(async move {
-            log::info!("config file updater started");
-            let conf_path = app_info_inner.read().await.config_path().clone();
-            while rx.recv().await.is_some() && app_info_inner.running().await {
-                if let Ok(data) = fs::read(&conf_path).await {
-                    #[cfg(debug_assertions)]
-                    log::info!("config file updated, parsing...");
-                    match serde_json::from_slice(&data) {
-                        Ok(conf) => {
-                            *app_info.write().await.config_mut() = conf;
-                            #[cfg(debug_assertions)]
-                            log::info!("config updated successfully");
-                            #[cfg(feature = "sys-notify")]
-                            crate::daemon::notify("配置文件已更新").await;
-                        }
-                        Err(e) => {
-                            log::error!("Error parsing config: {}", e);
-                        }
-                    };
-                }
-            }
-            log::info!("config file updater stopped");
-        });
+                Err(e) => log::error!("watch err:{:?}", e),
+            })?;
         watcher.watch(
-            self.read().await.config_path().parent().unwrap(),
+            conf_path.parent().unwrap(),
             notify::RecursiveMode::NonRecursive,
         )?;
 
-        Ok((watcher, handle))
+        let shutdown = self.read().await.shutdown_token();
+        log::info!("config file updater started");
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                event = rx.recv() => if event.is_none() {
+                    break;
+                },
+            }
+            // wait until the burst of events of a single save is over
+            while let Ok(Some(_)) = time::timeout(RELOAD_DEBOUNCE, rx.recv()).await {}
+
+            let data = match fs::read(&conf_path).await {
+                Ok(data) => data,
+                Err(e) => {
+                    log::error!("Error reading config: {}", e);
+                    continue;
+                }
+            };
+            if data.is_empty() {
+                log::warn!("config file is empty, ignored");
+                continue;
+            }
+            let (mut conf, issues) = match Config::load(&data) {
+                Ok(loaded) => loaded,
+                Err(issues) => {
+                    reject_reload(&issues).await;
+                    continue;
+                }
+            };
+
+            let mut app_info_write = self.write().await;
+            // credentials edited by hand lift the hold like `set_user` does
+            if app_info_write.config().user() != conf.user() {
+                conf.set_login_hold(None);
+            }
+            let changes = app_info_write.config().changes(&conf);
+            if changes.is_empty() {
+                // our own save, or a write that didn't change anything, any
+                // issues were reported when the config was loaded
+                #[cfg(debug_assertions)]
+                log::info!("config file unchanged");
+                continue;
+            }
+            if !issues.is_empty() {
+                drop(app_info_write);
+                reject_reload(&issues).await;
+                continue;
+            }
+            *app_info_write.config_mut() = conf;
+            app_info_write.set_config_issues(vec![]);
+            METRICS.config_reloaded();
+            app_info_write.emit(Event::ConfigReloaded {
+                changes: changes.iter().map(|c| c.to_string()).collect(),
+            });
+            drop(app_info_write);
+            log::info!("config updated: {}", changes.join(", "));
+            #[cfg(feature = "sys-notify")]
+            crate::daemon::notify(&format!("配置文件已更新: {}", changes.join(", "))).await;
+        }
+        log::info!("config file updater stopped");
+        drop(watcher);
+        Ok(())
     }
 
     pub async fn running(&self) -> bool {
         self.0.read().await.running()
     }
 }
+
+/// Keep the running config after an edit with `issues`, the daemon keeps
+/// going on a config that worked rather than a half broken one.
+#[cfg(feature = "auto-update")]
+async fn reject_reload(issues: &[ConfigIssue]) {
+    for issue in issues.iter() {
+        log::error!("invalid config: {}", issue);
+    }
+    #[cfg(feature = "sys-notify")]
+    crate::daemon::notify(&format!("配置文件有误，未重新加载: {}", issues[0])).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use api::auth::{Suffix, UserInfo};
+
+    use super::{Config, LoginHold};
+
+    #[test]
+    fn login_hold_survives_reload_until_credentials_change() {
+        let mut conf = Config::default();
+        conf.set_user(UserInfo::new("2108114040", "wrong", Suffix::ChinaMobile));
+        conf.set_login_hold(Some(LoginHold {
+            user_id: "2108114040".into(),
+            reason: "账号或密码错误".into(),
+            until: None,
+        }));
+        let saved = serde_json::to_vec(&conf).unwrap();
+        let mut conf = Config::check(&saved).unwrap();
+        assert!(conf.login_hold().is_some());
+        conf.set_user(UserInfo::new("2108114040", "wrong", Suffix::ChinaMobile));
+        assert!(conf.login_hold().is_some());
+        conf.set_user(UserInfo::new("2108114040", "right", Suffix::ChinaMobile));
+        assert!(conf.login_hold().is_none());
+    }
+
+    #[test]
+    fn loads_despite_issues() {
+        let src = br#"{"user": {"id": "2108", "password": "pwd", "suffix": "@yd"}, "listen": "0.0.0.0:11451"}"#;
+        assert!(Config::check(src).is_err());
+        let (conf, issues) = Config::load(src).unwrap();
+        assert_eq!(conf.user().map(UserInfo::id), Some("2108"));
+        let paths: Vec<_> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(paths, ["$.user.id", "$.listen"]);
+        assert!(Config::load(br#"{"retry_interval": "soon"}"#).is_err());
+    }
+}
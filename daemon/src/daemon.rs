@@ -1,27 +1,30 @@
 use std::time::Duration;
 
-use api::auth::{
-    auth_async::{auth, get_auth_info, get_index_page},
-    AuthError, UserInfo,
-};
-#[cfg(feature = "sys-notify")]
-use notify::Watcher;
-use reqwest::ClientBuilder;
-use tokio::{
-    runtime::Handle,
-    task::{self, JoinHandle, LocalSet},
-    time,
+use api::{
+    auth::{auth_async::Network, AuthError, AuthFailure, Session, UserInfo},
+    logout::{logout_session_with, LogoutError},
 };
+use tokio::time;
 
 use crate::{
-    config::{AppConfig, AppInfo, GlobalAppInfo},
-    Error,
+    account,
+    config::{unix_now, AppConfig, AppInfo, AppState, GlobalAppInfo, LoginHold},
+    gateway, keepalive,
+    metrics::{self, METRICS},
+    schedule, Error,
 };
 
-pub async fn check_autewifi() -> bool {
-    let resp = ClientBuilder::new()
-        .build()
-        .unwrap()
+/// Logout server of the campus network, used until a login saved the real one
+const DEFAULT_LOGOUT_URL_BASE: &str = "http://10.101.2.205";
+/// how long auto-login waits after the portal refused for good, unless the
+/// account changes first
+const PERMANENT_PAUSE: Duration = Duration::from_secs(30 * 60);
+/// shortest wait before retrying once the portal asked us to slow down
+const RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+pub async fn check_autewifi(network: &Network) -> bool {
+    let resp = network
+        .client()
         .get("http://192.168.0.1")
         .timeout(Duration::from_secs(1))
         .send()
@@ -30,7 +33,7 @@ pub async fn check_autewifi() -> bool {
     match resp {
         Ok(resp) => resp
             .await
-            .map(|r| r.contains("location.replace(\"http://10."))
+            .map(|r| api::auth::portal_redirect(&r).is_some())
             .unwrap_or(false),
         Err(e) => {
             if !e.is_timeout() {
@@ -48,10 +51,22 @@ pub enum Signal {
 
 #[cfg(feature = "sys-notify")]
 pub async fn notify(msg: &str) {
+    show_notification(msg, notify_rust::Timeout::Default).await
+}
+
+/// Like [`notify`], but stays until dismissed, for what needs the user to
+/// do something.
+#[cfg(feature = "sys-notify")]
+pub async fn notify_persistent(msg: &str) {
+    show_notification(msg, notify_rust::Timeout::Never).await
+}
+
+#[cfg(feature = "sys-notify")]
+async fn show_notification(msg: &str, timeout: notify_rust::Timeout) {
     use notify_rust::Notification;
 
     let mut noti = Notification::new();
-    let noti = noti.subtitle("Htu Net Login").summary(msg);
+    let noti = noti.subtitle("Htu Net Login").summary(msg).timeout(timeout);
     let result_r = {
         #[cfg(target_os = "windows")]
         {
@@ -68,109 +83,322 @@ pub async fn notify(msg: &str) {
     }
 }
 
-pub async fn start() -> Result<(GlobalAppInfo, JoinHandle<()>), Error> {
-    let appinfo = AppInfo::load_or_create().await?.global();
-    #[cfg(feature = "auto-update")]
-    let (mut watcher, notify_handle) =
-        appinfo.run_auto_update().await.map_err(Error::FileNotify)?;
-
-    let appinfo_inner = appinfo.clone();
-    let handle = task::spawn_blocking(move || {
-        let appinfo = appinfo_inner;
-        let rt = Handle::current();
-        rt.block_on(async move {
-            let local = LocalSet::new();
-            let res = local
-                .run_until(async move {
-                    log::info!("running login thread");
-                    task::spawn_local(async move {
-                        let mut success = true;
-                        while appinfo.running().await {
-                            if !check_autewifi().await {
-                                continue;
-                            }
-
-                            let user = appinfo.read().await.config().user().cloned();
-                            let Some(user) = user else {
-                                time::sleep(Duration::from_secs(5)).await;
-                                continue;
-                            };
-                            match login(user).await {
-                                Ok(url) => {
-                                    success = true;
-                                    let mut appinfo_write = appinfo.write().await;
-                                    appinfo_write.config_mut().set_last_url(url.last_url);
-                                    appinfo_write
-                                        .config_mut()
-                                        .set_logout_url_base(url.logout_url_base);
-                                    drop(appinfo_write);
-                                    let _ = appinfo.read().await.save().await;
-                                    log::info!("login success");
-                                }
-                                Err(e) => {
-                                    let AuthError::AuthFailed { msg } = e else {
-                                        log::error!("login error: {}", e);
-                                        continue;
-                                    };
-
-                                    if success {
-                                        #[cfg(feature = "sys-notify")]
-                                        notify(&format!("登录失败: {}", msg)).await;
-                                        log::error!("login error: {}", msg);
-                                        success = false;
-                                    }
-                                    time::sleep(Duration::from_secs(5)).await;
-                                }
-                            };
-                        }
-                        log::info!("login thread exit");
-                        #[cfg(feature = "auto-update")]
-                        watcher
-                            .unwatch(appinfo.read().await.config_path().parent().unwrap())
-                            .unwrap()
-                    })
-                    .await
-                })
-                .await;
-            if let Err(e) = res {
-                log::error!("daemon error: {}", e);
+/// Load the config and prepare the daemon, the subsystems are started by
+/// [`crate::start`].
+pub async fn start() -> Result<GlobalAppInfo, Error> {
+    let (mut appinfo, issues) = AppInfo::load_or_create().await?;
+    if !issues.is_empty() {
+        // older versions took these configs, refusing them now would leave
+        // the machine offline
+        for issue in &issues {
+            log::warn!("config loaded despite: {}", issue);
+        }
+        #[cfg(feature = "sys-notify")]
+        notify(&format!("配置文件有误，请检查: {}", issues[0])).await;
+    }
+    appinfo.set_config_issues(issues);
+    let appinfo = appinfo.global();
+    api::auth::observe_steps(metrics::observe_step);
+    Ok(appinfo)
+}
+
+/// Keep the campus network connected until shutdown.
+pub async fn run(appinfo: GlobalAppInfo) {
+    let shutdown = appinfo.read().await.shutdown_token();
+    log::info!("running login task");
+    // drop whatever request is in flight once shutdown starts
+    tokio::select! {
+        _ = shutdown.cancelled() => (),
+        _ = login_loop(&appinfo) => (),
+    }
+    log::info!("login task exit");
+}
+
+async fn login_loop(appinfo: &GlobalAppInfo) {
+    let mut success = true;
+    let mut kicked = false;
+    while appinfo.running().await {
+        let gateway = appinfo.read().await.config().gateway().cloned();
+        let network = match gateway {
+            Some(gateway) => match gateway::keep_alive(appinfo, &gateway).await {
+                Some(network) => network,
+                None => continue,
+            },
+            None => {
+                let resolver = appinfo.read().await.config().resolver();
+                let network = Network::default().with_resolver(resolver);
+                if !check_autewifi(&network).await {
+                    keepalive::hold(appinfo, &network).await;
+                    continue;
+                }
+                network
+            }
+        };
+        METRICS.set_online(false);
+
+        let user = appinfo.read().await.config().user().cloned();
+        let Some(user) = user else {
+            time::sleep(Duration::from_secs(5)).await;
+            continue;
+        };
+        if let Some(wait) = schedule::paused(appinfo).await {
+            time::sleep(wait).await;
+            continue;
+        }
+        let pause = appinfo.read().await.login_pause();
+        if let Some(pause) = pause {
+            // wake up now and then, changing the account lifts the pause
+            let recheck = appinfo.read().await.config().retry_interval();
+            let wait = pause.until.map_or(recheck, |until| {
+                Duration::from_secs(until.saturating_sub(unix_now())).min(recheck)
+            });
+            time::sleep(wait).await;
+            continue;
+        }
+        let lock = appinfo.read().await.login_lock();
+        let Ok(_flight) = lock.clone().try_lock_owned() else {
+            // a `/login` request is at it, check again once it's done
+            drop(lock.lock().await);
+            continue;
+        };
+        match login(appinfo, &network, user.clone()).await {
+            Ok(session) => {
+                success = true;
+                kicked = false;
+                logged_in(appinfo, session).await;
+                log::info!("login success");
             }
-        })
+            Err(e) => {
+                appinfo.write().await.login_failed(&e);
+                let (AuthError::AuthFailed { msg }, Some(failure)) = (&e, e.failure()) else {
+                    log::error!("login error: {}", e);
+                    continue;
+                };
+                // once per failed streak, so that two devices don't take
+                // turns kicking each other
+                if failure == AuthFailure::DeviceLimit
+                    && !kicked
+                    && appinfo.read().await.config().kick_oldest_on_limit()
+                {
+                    kicked = true;
+                    if account::kick_oldest(appinfo, &user).await {
+                        continue;
+                    }
+                }
+                if hold_if_permanent(appinfo, &user, &e).await {
+                    success = false;
+                    continue;
+                }
+
+                if success {
+                    #[cfg(feature = "sys-notify")]
+                    notify(&format!("登录失败: {}", msg)).await;
+                    log::error!("login error: {}", msg);
+                    success = false;
+                }
+                let mut interval = appinfo.read().await.config().retry_interval();
+                if failure == AuthFailure::RateLimited {
+                    interval = interval.max(RATE_LIMIT_WAIT);
+                }
+                time::sleep(interval).await;
+            }
+        };
+    }
+}
+
+/// Hold auto-login back when retrying after `e` can't help, true if it did.
+/// Refused credentials wait for the account to change or an explicit login,
+/// as retrying them gets the account locked, or keeps it locked.
+pub(crate) async fn hold_if_permanent(
+    appinfo: &GlobalAppInfo,
+    user: &UserInfo,
+    e: &AuthError,
+) -> bool {
+    let (AuthError::AuthFailed { msg }, Some(failure)) = (e, e.failure()) else {
+        return false;
+    };
+    if !failure.is_permanent() {
+        return false;
+    }
+    let until = if failure.is_credential() {
+        log::error!(
+            "login refused ({}), auto-login stopped until the account changes",
+            msg
+        );
+        #[cfg(feature = "sys-notify")]
+        notify_persistent(&format!(
+            "登录失败: {}，已停止自动登录以免账号被锁定。请修改账号或手动重新登录",
+            failure
+        ))
+        .await;
+        None
+    } else {
+        log::error!(
+            "login refused ({}), pausing auto-login for {:?}",
+            msg,
+            PERMANENT_PAUSE
+        );
+        #[cfg(feature = "sys-notify")]
+        notify(&format!(
+            "登录失败: {}，{} 分钟内不再自动登录",
+            failure,
+            PERMANENT_PAUSE.as_secs() / 60
+        ))
+        .await;
+        Some(unix_now() + PERMANENT_PAUSE.as_secs())
+    };
+    appinfo.write().await.pause_login(LoginHold {
+        user_id: user.id().to_string(),
+        reason: failure.to_string(),
+        until,
     });
+    // a restart, e.g. at boot, must not retry refused credentials
+    if let Err(e) = appinfo.read().await.save().await {
+        log::warn!("failed to save the login hold: {}", e);
+    }
+    true
+}
 
-    #[cfg(not(feature = "auto-update"))]
-    return Ok((appinfo, handle));
-    #[cfg(feature = "auto-update")]
-    Ok((
-        appinfo,
-        tokio::spawn(async move {
-            tokio::try_join!(handle, notify_handle).unwrap();
-        }),
-    ))
+/// Base url of the logout server, which also serves the account details.
+pub(crate) async fn logout_url_base(appinfo: &GlobalAppInfo) -> String {
+    appinfo
+        .read()
+        .await
+        .config()
+        .logout_url_base()
+        .unwrap_or(DEFAULT_LOGOUT_URL_BASE)
+        .to_string()
 }
 
-pub async fn login_net(user: &UserInfo) -> Result<(), AuthError> {
-    let url = get_index_page(false).await?;
-    let auth_info = get_auth_info(&url).await?;
-    auth(url, auth_info, user).await
+/// Log out of the session kept from the last login,
+/// [`LogoutError::NotLoggedIn`] when there is none. The logout endpoint is
+/// never guessed, configs from before sessions were kept log in again first.
+pub async fn logout(appinfo: &GlobalAppInfo) -> Result<(), LogoutError> {
+    let endpoint = appinfo
+        .read()
+        .await
+        .config()
+        .session()
+        .map(|session| session.logout_url.clone());
+    let Some(endpoint) = endpoint else {
+        return Err(LogoutError::NotLoggedIn);
+    };
+    let network = gateway::network(appinfo).await;
+    logout_session_with(network.client(), Some(&endpoint)).await?;
+    METRICS.set_online(false);
+    let mut appinfo_write = appinfo.write().await;
+    let conf = appinfo_write.config_mut();
+    conf.set_session(None);
+    conf.set_logout_url_base(None);
+    appinfo_write.logged_out();
+    drop(appinfo_write);
+    let _ = appinfo.read().await.save().await;
+    Ok(())
 }
 
-struct LoginUrls {
-    logout_url_base: String,
-    last_url: String,
+/// Resolves once the OS asks the daemon to stop: SIGTERM or SIGINT on unix,
+/// ctrl-c, console close, logoff or system shutdown on windows.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let (mut term, mut int) = match (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+        ) {
+            (Ok(term), Ok(int)) => (term, int),
+            (Err(e), _) | (_, Err(e)) => {
+                log::error!("failed to listen for signals: {}", e);
+                return std::future::pending().await;
+            }
+        };
+        tokio::select! {
+            _ = term.recv() => log::info!("received SIGTERM"),
+            _ = int.recv() => log::info!("received SIGINT"),
+        }
+    }
+    #[cfg(windows)]
+    {
+        use tokio::signal::windows;
+
+        let signals = (
+            windows::ctrl_c(),
+            windows::ctrl_close(),
+            windows::ctrl_logoff(),
+            windows::ctrl_shutdown(),
+        );
+        let (mut c, mut close, mut logoff, mut shutdown) = match signals {
+            (Ok(c), Ok(close), Ok(logoff), Ok(shutdown)) => (c, close, logoff, shutdown),
+            (Err(e), ..) | (_, Err(e), ..) | (.., Err(e), _) | (.., Err(e)) => {
+                log::error!("failed to listen for console events: {}", e);
+                return std::future::pending().await;
+            }
+        };
+        tokio::select! {
+            _ = c.recv() => log::info!("received ctrl-c"),
+            _ = close.recv() => log::info!("console closed"),
+            _ = logoff.recv() => log::info!("user logging off"),
+            _ = shutdown.recv() => log::info!("system shutting down"),
+        }
+    }
+}
+
+pub async fn login_net(
+    appinfo: &GlobalAppInfo,
+    network: &Network,
+    user: &UserInfo,
+) -> Result<Session, AuthError> {
+    let url = network.get_index_page(false).await?;
+    let context = url.context();
+    // tells which AC and VLAN this machine is on when logins go wrong
+    log::info!("portal redirect: {}", context);
+    appinfo.write().await.set_portal(context);
+    let auth_info = network.get_auth_info(&url).await?;
+    network.auth(url, auth_info, user).await
+}
+
+/// Record a successful login and keep its session for logging out.
+pub(crate) async fn logged_in(appinfo: &GlobalAppInfo, session: Session) {
+    let mut appinfo_write = appinfo.write().await;
+    let conf = appinfo_write.config_mut();
+    conf.set_last_url(session.url.clone());
+    conf.set_logout_url_base(Some(session.logout_url_base.clone()));
+    conf.set_session(Some(session));
+    appinfo_write.login_succeeded();
+    drop(appinfo_write);
+    let _ = appinfo.read().await.save().await;
 }
 
-async fn login(user: UserInfo) -> Result<LoginUrls, AuthError> {
-    let url = get_index_page(false).await?;
-    let auth_info = get_auth_info(&url).await?;
-    let last_url = url.url.clone();
-    let logout_url = auth_info.logout_url_root.clone();
-    auth(url, auth_info, &user).await?;
+async fn login(
+    appinfo: &GlobalAppInfo,
+    network: &Network,
+    user: UserInfo,
+) -> Result<Session, AuthError> {
+    let session = login_net(appinfo, network, &user).await?;
     log::info!("connected to htu-net");
     #[cfg(feature = "sys-notify")]
     notify("已连接到校园网").await;
-    Ok(LoginUrls {
-        logout_url_base: logout_url,
-        last_url,
-    })
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use api::logout::LogoutError;
+
+    use crate::config::{AppConfig, AppInfo, Config};
+
+    #[tokio::test]
+    async fn logout_without_session() {
+        // configs from before sessions were kept only have the base
+        let mut conf = Config::default();
+        conf.set_logout_url_base(Some("http://10.101.2.205".into()));
+        let appinfo = AppInfo::new(conf, PathBuf::new()).global();
+        assert!(matches!(
+            super::logout(&appinfo).await,
+            Err(LogoutError::NotLoggedIn)
+        ));
+    }
 }
@@ -0,0 +1,166 @@
+"use strict";
+
+const API = "/api/v1";
+const REFRESH_MS = 5000;
+
+const $ = (id) => document.getElementById(id);
+
+const TOKEN_KEY = "htu-net-token";
+
+/// Call the daemon api, resolves to `data` of the envelope or throws its error.
+/// Asks for the api token when the daemon wants one, i.e. when it is opened
+/// from another machine.
+async function call(method, path, body, retried) {
+  const init = { method, headers: {} };
+  if (body !== undefined) {
+    init.headers["Content-Type"] = "application/json";
+    init.body = JSON.stringify(body);
+  }
+  const token = localStorage.getItem(TOKEN_KEY);
+  if (token) {
+    init.headers["Authorization"] = "Bearer " + token;
+  }
+  const resp = await fetch(API + path, init);
+  const envelope = await resp.json();
+  if (!envelope.ok) {
+    if (envelope.error && envelope.error.code === "unauthorized" && !retried) {
+      const token = prompt("请输入访问令牌 (api_token)");
+      if (token) {
+        localStorage.setItem(TOKEN_KEY, token);
+        return call(method, path, body, true);
+      }
+    }
+    throw new Error(envelope.error ? envelope.error.message : resp.statusText);
+  }
+  return envelope.data;
+}
+
+let toastTimer;
+function toast(msg, error) {
+  const el = $("toast");
+  el.textContent = msg;
+  el.className = error ? "err" : "";
+  el.hidden = false;
+  clearTimeout(toastTimer);
+  toastTimer = setTimeout(() => (el.hidden = true), 4000);
+}
+
+function formatTime(unix) {
+  return unix ? new Date(unix * 1000).toLocaleString() : "-";
+}
+
+async function refreshStatus() {
+  try {
+    const status = await call("GET", "/status");
+    $("daemon").textContent = "运行中";
+    $("daemon").className = "ok";
+    $("version").textContent = "v" + status.version;
+    $("account").textContent = status.account || "未设置";
+    $("last-login").textContent = formatTime(status.last_login);
+    $("last-error").textContent = status.last_error || "-";
+    const crash = status.crashes && status.crashes[status.crashes.length - 1];
+    $("last-crash").textContent = crash
+      ? `${formatTime(crash.at)} ${crash.subsystem}: ${crash.reason}`
+      : "-";
+  } catch (e) {
+    $("daemon").textContent = "无法连接";
+    $("daemon").className = "err";
+  }
+}
+
+async function refreshHistory() {
+  let records;
+  try {
+    records = await call("GET", "/history");
+  } catch (e) {
+    return;
+  }
+  const rows = records.map((r) => {
+    const tr = document.createElement("tr");
+    const result = document.createElement("td");
+    result.textContent = r.ok ? "成功" : "失败";
+    result.className = r.ok ? "ok" : "err";
+    const at = document.createElement("td");
+    at.textContent = formatTime(r.at);
+    const msg = document.createElement("td");
+    msg.textContent = r.msg || "";
+    tr.append(at, result, msg);
+    return tr;
+  });
+  $("history").replaceChildren(...rows);
+}
+
+function refresh() {
+  refreshStatus();
+  refreshHistory();
+}
+
+async function loadUser() {
+  try {
+    const user = await call("GET", "/user");
+    if (user) {
+      const fields = $("user-form").elements;
+      fields.id.value = user.id;
+      fields.password.value = user.password;
+      fields.suffix.value = user.suffix;
+    }
+  } catch (e) {
+    toast("获取账号失败: " + e.message, true);
+  }
+}
+
+/// Disable `button` while `action` runs.
+async function busy(button, action) {
+  button.disabled = true;
+  try {
+    await action();
+  } finally {
+    button.disabled = false;
+    refresh();
+  }
+}
+
+$("login").addEventListener("click", (ev) =>
+  busy(ev.target, async () => {
+    try {
+      await call("POST", "/login");
+      toast("登录成功");
+    } catch (e) {
+      toast("登录失败: " + e.message, true);
+    }
+  }),
+);
+
+$("logout").addEventListener("click", (ev) =>
+  busy(ev.target, async () => {
+    try {
+      await call("POST", "/logout");
+      toast("已注销");
+    } catch (e) {
+      toast("注销失败: " + e.message, true);
+    }
+  }),
+);
+
+$("user-form").addEventListener("submit", (ev) => {
+  ev.preventDefault();
+  const form = ev.target;
+  const fields = form.elements;
+  const user = {
+    id: fields.id.value.trim(),
+    password: fields.password.value,
+    suffix: fields.suffix.value,
+  };
+  busy(form.querySelector("button"), async () => {
+    try {
+      await call("POST", "/user", user);
+      toast("账号已保存");
+    } catch (e) {
+      toast("保存失败:\n" + e.message, true);
+    }
+  });
+});
+
+loadUser();
+refresh();
+setInterval(refresh, REFRESH_MS);
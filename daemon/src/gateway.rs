@@ -0,0 +1,343 @@
+//! Gateway mode: the daemon runs on a box doing NAT for a LAN, keeps its WAN
+//! interface logged in and tells LAN clients why the internet is down.
+
+use std::{
+    convert::Infallible,
+    error::Error as StdError,
+    fmt::Display,
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+use api::auth::auth_async::Network;
+use hyper::{header, Body, Method, Request, Response};
+use proto::{Event, GatewayStatus, Status};
+use tokio::{sync::broadcast::error::RecvError, time};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::{unix_now, AppConfig, AppState, GatewayConfig, GlobalAppInfo},
+    daemon::check_autewifi,
+    keepalive,
+    metrics::METRICS,
+    Error,
+};
+
+/// seconds between reloads of the status page
+const REFRESH_SECS: u64 = 30;
+
+#[derive(Debug)]
+pub enum GatewayError {
+    Interfaces(io::Error),
+    NoInterface(String),
+    NoAddress(String),
+}
+
+impl Display for GatewayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Interfaces(e) => write!(f, "failed to list network interfaces: {}", e),
+            Self::NoInterface(name) => write!(f, "interface `{}` not found", name),
+            Self::NoAddress(name) => write!(f, "interface `{}` has no ipv4 address", name),
+        }
+    }
+}
+
+impl StdError for GatewayError {}
+
+/// IPv4 address the WAN interface has right now, the portal only speaks
+/// ipv4.
+fn wan_address(conf: &GatewayConfig) -> Result<IpAddr, GatewayError> {
+    let name = conf.wan_interface();
+    let addrs: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .map_err(GatewayError::Interfaces)?
+        .into_iter()
+        .filter(|iface| iface.name == name)
+        .map(|iface| iface.ip())
+        .collect();
+    if addrs.is_empty() {
+        return Err(GatewayError::NoInterface(name.to_string()));
+    }
+    addrs
+        .into_iter()
+        .find(IpAddr::is_ipv4)
+        .ok_or_else(|| GatewayError::NoAddress(name.to_string()))
+}
+
+/// Network bound to the current address of the WAN interface. Build one per
+/// attempt instead of keeping it, a DHCP renew may move the address.
+pub fn wan_network(conf: &GatewayConfig) -> Result<Network, GatewayError> {
+    wan_address(conf).map(Network::bind)
+}
+
+/// Whether `network` still goes out the way the config wants, false once
+/// the WAN address changed or gateway mode was turned on or off.
+pub(crate) async fn still_bound(appinfo: &GlobalAppInfo, network: &Network) -> bool {
+    let gateway = appinfo.read().await.config().gateway().cloned();
+    match gateway {
+        Some(conf) => wan_address(&conf).ok() == network.local_address(),
+        None => network.local_address().is_none(),
+    }
+}
+
+/// Network to reach the portal through: the WAN in gateway mode, falling
+/// back to the system's routes when the WAN can't be used.
+pub(crate) async fn network(appinfo: &GlobalAppInfo) -> Network {
+    let (gateway, resolver) = {
+        let appinfo = appinfo.read().await;
+        let conf = appinfo.config();
+        (conf.gateway().cloned(), conf.resolver())
+    };
+    let network = match gateway.map(|gateway| wan_network(&gateway)) {
+        Some(Ok(network)) => network,
+        Some(Err(e)) => {
+            log::warn!("{}, using the default route", e);
+            Network::default()
+        }
+        None => Network::default(),
+    };
+    network.with_resolver(resolver)
+}
+
+/// Probe the internet through the WAN. Returns the network to log in through
+/// when the portal wants a login, otherwise records why not and waits until
+/// it is time for the next probe, or for the session to drop while online.
+pub(crate) async fn keep_alive(appinfo: &GlobalAppInfo, conf: &GatewayConfig) -> Option<Network> {
+    let mut status = GatewayStatus {
+        interface: conf.wan_interface().to_string(),
+        address: None,
+        online: false,
+        last_probe: Some(unix_now()),
+        error: None,
+    };
+    let resolver = appinfo.read().await.config().resolver();
+    let network = match wan_network(conf) {
+        Ok(network) => {
+            let network = network.with_resolver(resolver);
+            status.address = network.local_address().map(|addr| addr.to_string());
+            status.online = network.is_online().await;
+            status.last_probe = Some(unix_now());
+            if !status.online && !check_autewifi(&network).await {
+                status.error = Some("上游网络不可用，未找到校园网认证页面".to_string());
+            }
+            Some(network)
+        }
+        Err(e) => {
+            status.error = Some(e.to_string());
+            None
+        }
+    };
+    METRICS.set_online(status.online);
+    let (online, error) = (status.online, status.error.clone());
+    let prev = appinfo.write().await.set_gateway(status);
+    if prev.map(|prev| (prev.online, prev.error)) != Some((online, error.clone())) {
+        match &error {
+            Some(e) => log::warn!("wan {}: {}", conf.wan_interface(), e),
+            None => log::info!("wan {} online: {}", conf.wan_interface(), online),
+        }
+    }
+    if online {
+        if let Some(network) = &network {
+            keepalive::hold(appinfo, network).await;
+        }
+        return None;
+    }
+    if error.is_some() {
+        let interval = appinfo.read().await.config().retry_interval();
+        time::sleep(interval).await;
+        return None;
+    }
+    network
+}
+
+/// Serve the status page to LAN clients until shutdown, following the config
+/// as it is reloaded: the page starts, moves and stops along with gateway
+/// mode and `status_listen`.
+pub async fn serve_status(conf: GlobalAppInfo) -> Result<(), Error> {
+    let shutdown = conf.read().await.shutdown_token();
+    let mut events = conf.read().await.subscribe();
+    while !shutdown.is_cancelled() {
+        let addr = status_addr(&conf).await;
+        let stop = shutdown.child_token();
+        let server = serve_status_at(addr, conf.clone(), stop.clone());
+        tokio::pin!(server);
+        loop {
+            tokio::select! {
+                result = &mut server => {
+                    result?;
+                    break;
+                }
+                event = events.recv() => match event {
+                    Ok(Event::ConfigReloaded { .. }) | Err(RecvError::Lagged(_)) => {
+                        if status_addr(&conf).await != addr {
+                            stop.cancel();
+                        }
+                    }
+                    Err(RecvError::Closed) => shutdown.cancelled().await,
+                    Ok(_) => (),
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where the config wants the status page, `None` outside gateway mode.
+async fn status_addr(conf: &GlobalAppInfo) -> Option<SocketAddr> {
+    conf.read()
+        .await
+        .config()
+        .gateway()
+        .map(|g| g.status_addr())
+}
+
+async fn serve_status_at(
+    addr: Option<SocketAddr>,
+    conf: GlobalAppInfo,
+    stop: CancellationToken,
+) -> Result<(), Error> {
+    let Some(addr) = addr else {
+        stop.cancelled().await;
+        return Ok(());
+    };
+    let make_svc = hyper::service::make_service_fn(move |_| {
+        let conf = conf.clone();
+        async move {
+            Ok::<_, Infallible>(hyper::service::service_fn(move |req| {
+                status_page(req, conf.clone())
+            }))
+        }
+    });
+    let server = hyper::Server::try_bind(&addr)
+        .map_err(Error::Hyper)?
+        .serve(make_svc);
+    log::info!("gateway status page on {}", server.local_addr());
+    server
+        .with_graceful_shutdown(async move { stop.cancelled().await })
+        .await
+        .map_err(Error::Hyper)?;
+    log::info!("gateway status page on {} stopped", addr);
+    Ok(())
+}
+
+async fn status_page(
+    req: Request<Body>,
+    conf: GlobalAppInfo,
+) -> Result<Response<Body>, hyper::http::Error> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return Response::builder()
+            .status(405)
+            .header(header::ALLOW, "GET, HEAD")
+            .body(Body::empty());
+    }
+    let status = conf.read().await.status();
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(render(&status, unix_now())))
+}
+
+/// The page only says what LAN clients need to know, not the account.
+fn render(status: &Status, now: u64) -> String {
+    let (title, reason) = match &status.gateway {
+        None => ("正在检查网络", None),
+        Some(gateway) if gateway.online => ("网络正常", None),
+        Some(gateway) => (
+            "网络已断开",
+            Some(
+                gateway
+                    .error
+                    .clone()
+                    .or_else(|| status.last_error.clone())
+                    .unwrap_or_else(|| "正在登录校园网，请稍候".to_string()),
+            ),
+        ),
+    };
+    let reason = reason
+        .map(|reason| format!("<p>原因: {}</p>\n", escape(&reason)))
+        .unwrap_or_default();
+    let last_login = match status.last_login {
+        Some(at) => format!("{} 分钟前", now.saturating_sub(at) / 60),
+        None => "无".to_string(),
+    };
+    format!(
+        r#"<!doctype html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="{refresh}">
+<title>校园网状态</title>
+</head>
+<body style="font-family: sans-serif; max-width: 32em; margin: 2em auto; padding: 0 1em">
+<h1>{title}</h1>
+{reason}<p>上次登录: {last_login}</p>
+<p><small>页面每 {refresh} 秒自动刷新</small></p>
+</body>
+</html>
+"#,
+        refresh = REFRESH_SECS,
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::{GatewayStatus, Status};
+
+    use crate::config::GatewayConfig;
+
+    use super::{render, wan_network, GatewayError};
+
+    #[test]
+    fn page_explains_outage() {
+        let mut status = Status {
+            version: "0.1.2".into(),
+            account: Some("2108114040".into()),
+            last_login: Some(1700000000),
+            last_error: Some("<密码错误>".into()),
+            crashes: vec![],
+            tls_fingerprint: None,
+            gateway: Some(GatewayStatus {
+                interface: "eth0".into(),
+                address: Some("10.102.3.4".into()),
+                online: false,
+                last_probe: Some(1700000600),
+                error: None,
+            }),
+            keepalive: None,
+            schedule: None,
+            paused: None,
+            session: None,
+            portal: None,
+            connectivity: None,
+            config_issues: vec![],
+        };
+        let page = render(&status, 1700000600);
+        assert!(page.contains("网络已断开"));
+        assert!(page.contains("原因: &lt;密码错误&gt;"));
+        assert!(page.contains("10 分钟前"));
+        assert!(!page.contains("2108114040"));
+
+        status.gateway.as_mut().unwrap().online = true;
+        let page = render(&status, 1700000600);
+        assert!(page.contains("网络正常"));
+        assert!(!page.contains("原因"));
+    }
+
+    #[test]
+    fn missing_interface() {
+        let conf: GatewayConfig =
+            serde_json::from_str(r#"{"wan_interface": "htu-net-none0"}"#).unwrap();
+        assert!(matches!(
+            wan_network(&conf),
+            Err(GatewayError::NoInterface(name)) if name == "htu-net-none0"
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+//! Makes sure only one daemon runs per user.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::{self, File, TryLockError},
+    io,
+    path::{Path, PathBuf},
+};
+
+const LOCK_FILE: &str = "daemon.lock";
+// windows doesn't let other processes read a locked file, so the pid lives
+// next to it
+const PID_FILE: &str = "daemon.pid";
+
+#[derive(Debug)]
+pub enum LockError {
+    /// another daemon holds the lock, `pid` is unknown if it didn't write
+    /// its pid file yet
+    AlreadyRunning {
+        pid: Option<u32>,
+    },
+    Io(io::Error),
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyRunning { pid: Some(pid) } => {
+                write!(f, "daemon already running (pid {})", pid)
+            }
+            Self::AlreadyRunning { pid: None } => write!(f, "daemon already running"),
+            Self::Io(e) => write!(f, "failed to lock daemon instance: {}", e),
+        }
+    }
+}
+
+impl Error for LockError {}
+
+/// Held for the lifetime of the daemon, the OS releases it when the process
+/// exits, even after a crash.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+    pid_path: PathBuf,
+}
+
+impl InstanceLock {
+    pub fn acquire(dir: &Path) -> Result<Self, LockError> {
+        fs::create_dir_all(dir).map_err(LockError::Io)?;
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))
+            .map_err(LockError::Io)?;
+        let pid_path = dir.join(PID_FILE);
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                return Err(LockError::AlreadyRunning {
+                    pid: Self::running_pid(dir),
+                })
+            }
+            Err(TryLockError::Error(e)) => return Err(LockError::Io(e)),
+        }
+        fs::write(&pid_path, std::process::id().to_string()).map_err(LockError::Io)?;
+        Ok(Self {
+            _file: file,
+            pid_path,
+        })
+    }
+
+    /// Pid of the daemon holding the lock in `dir`, if any wrote one.
+    pub fn running_pid(dir: &Path) -> Option<u32> {
+        fs::read_to_string(dir.join(PID_FILE))
+            .ok()
+            .and_then(|pid| pid.trim().parse().ok())
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.pid_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InstanceLock, LockError};
+
+    #[test]
+    fn second_instance_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("htu-net-lock-{}", std::process::id()));
+        let lock = InstanceLock::acquire(&dir).unwrap();
+        match InstanceLock::acquire(&dir) {
+            Err(LockError::AlreadyRunning { pid }) => assert_eq!(pid, Some(std::process::id())),
+            res => panic!("expected AlreadyRunning, got {:?}", res),
+        }
+        drop(lock);
+        assert_eq!(InstanceLock::running_pid(&dir), None);
+        InstanceLock::acquire(&dir).unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,220 @@
+//! Keeps the portal session from timing out while idle by probing through
+//! it. Unless configured, the interval is learned from the sessions that
+//! dropped despite the probes.
+
+use std::time::Duration;
+
+use api::{
+    auth::auth_async::Network,
+    tool::{Family, Reachability},
+};
+use proto::{Connectivity, KeepaliveStatus};
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+use crate::{
+    config::{unix_now, AppState, GlobalAppInfo},
+    daemon::check_autewifi,
+    gateway,
+    metrics::METRICS,
+};
+
+/// how often to look for the portal while online
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+const MIN_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_INTERVAL: Duration = Duration::from_secs(300);
+/// successful probes in a row before trying a longer interval
+const GROW_AFTER: u32 = 30;
+
+#[derive(Debug, Clone)]
+pub struct KeepAlive {
+    learned: Duration,
+    /// shortest interval a session dropped at, never grow back to it
+    ceiling: Option<Duration>,
+    streak: u32,
+    drops: u32,
+    last_probe: Option<u64>,
+    /// we logged out ourselves, the next failed probe isn't a drop
+    logged_out: bool,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            learned: DEFAULT_INTERVAL,
+            ceiling: None,
+            streak: 0,
+            drops: 0,
+            last_probe: None,
+            logged_out: false,
+        }
+    }
+}
+
+impl KeepAlive {
+    /// The `fixed` interval from the config wins over the learned one.
+    pub fn interval(&self, fixed: Option<Duration>) -> Duration {
+        fixed.unwrap_or(self.learned)
+    }
+
+    /// Record a probe, only learning from it when the interval isn't fixed.
+    pub fn probed(&mut self, ok: bool, learn: bool) {
+        self.last_probe = Some(unix_now());
+        if !ok {
+            self.dropped(learn);
+            return;
+        }
+        self.logged_out = false;
+        self.streak += 1;
+        if learn && self.streak >= GROW_AFTER {
+            self.streak = 0;
+            let cap = self
+                .ceiling
+                .map_or(MAX_INTERVAL, |c| (c * 9 / 10).min(MAX_INTERVAL));
+            self.learned = (self.learned * 11 / 10).min(cap).max(self.learned);
+        }
+    }
+
+    /// Record a session that dropped between probes, as found by the portal
+    /// asking for a login again.
+    pub fn dropped(&mut self, learn: bool) {
+        if std::mem::take(&mut self.logged_out) {
+            return;
+        }
+        self.drops += 1;
+        self.streak = 0;
+        if learn {
+            self.ceiling = Some(self.ceiling.map_or(self.learned, |c| c.min(self.learned)));
+            self.learned = (self.learned * 2 / 3).max(MIN_INTERVAL);
+        }
+    }
+
+    pub fn logged_out(&mut self) {
+        self.logged_out = true;
+    }
+
+    pub fn status(&self, fixed: Option<Duration>) -> KeepaliveStatus {
+        KeepaliveStatus {
+            interval: self.interval(fixed).as_secs(),
+            learned: fixed.is_none(),
+            last_probe: self.last_probe,
+            drops: self.drops,
+        }
+    }
+}
+
+/// Hold the session for as long as we are online: look for the portal every
+/// [`CHECK_INTERVAL`] and probe through `network` every keep-alive interval.
+/// Returns once the portal wants a login again or a probe failed, i.e. the
+/// session dropped, or the way out changed.
+pub(crate) async fn hold(appinfo: &GlobalAppInfo, network: &Network) {
+    // not being online to begin with isn't a drop
+    if !probe(appinfo, network).await {
+        return;
+    }
+    let mut last_probe = Instant::now();
+    // a long keep-alive interval mustn't delay noticing a kick or a portal
+    // restart, those aren't up to the probes
+    let mut checks = time::interval(CHECK_INTERVAL);
+    checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let interval = appinfo.read().await.keepalive_interval();
+        tokio::select! {
+            _ = time::sleep_until(last_probe + interval) => {
+                if !gateway::still_bound(appinfo, network).await {
+                    log::info!("way out changed, binding again");
+                    return;
+                }
+                let ok = probe(appinfo, network).await;
+                last_probe = Instant::now();
+                appinfo.write().await.keepalive_probed(ok);
+                if !ok {
+                    log::warn!(
+                        "keep-alive probe failed after {:?}, session dropped",
+                        interval
+                    );
+                    return;
+                }
+            }
+            _ = checks.tick() => {
+                if check_autewifi(network).await {
+                    appinfo.write().await.keepalive_dropped();
+                    METRICS.set_online(false);
+                    log::warn!("portal is asking for a login again, session dropped");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Probe both address families through `network` and record what got
+/// through, returns whether IPv4 did, which is what the portal session is
+/// about and what `htu_net_online` reports.
+async fn probe(appinfo: &GlobalAppInfo, network: &Network) -> bool {
+    let (ipv4, ipv6) = tokio::join!(network.probe(Family::V4), network.probe(Family::V6));
+    let hijacked = [ipv4, ipv6].into_iter().find_map(|found| match found {
+        Reachability::Hijacked(addr) => Some(addr.to_string()),
+        _ => None,
+    });
+    appinfo.write().await.set_connectivity(Connectivity {
+        ipv4: ipv4 == Reachability::Online,
+        ipv6: ipv6 == Reachability::Online,
+        hijacked,
+        checked_at: unix_now(),
+    });
+    METRICS.set_online(ipv4 == Reachability::Online);
+    ipv4 == Reachability::Online
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{KeepAlive, DEFAULT_INTERVAL, GROW_AFTER, MIN_INTERVAL};
+
+    #[test]
+    fn learns_from_drops() {
+        let mut keepalive = KeepAlive::default();
+        keepalive.probed(false, true);
+        assert_eq!(keepalive.interval(None), Duration::from_secs(40));
+        for _ in 0..GROW_AFTER * 20 {
+            keepalive.probed(true, true);
+        }
+        // grows again, but stays clear of the interval that dropped
+        assert_eq!(keepalive.interval(None), DEFAULT_INTERVAL * 9 / 10);
+        for _ in 0..10 {
+            keepalive.probed(false, true);
+        }
+        assert_eq!(keepalive.interval(None), MIN_INTERVAL);
+        assert_eq!(keepalive.status(None).drops, 11);
+    }
+
+    #[test]
+    fn fixed_interval_is_kept() {
+        let mut keepalive = KeepAlive::default();
+        let fixed = Some(Duration::from_secs(90));
+        keepalive.probed(false, false);
+        assert_eq!(keepalive.interval(fixed), Duration::from_secs(90));
+        assert_eq!(keepalive.interval(None), DEFAULT_INTERVAL);
+        assert!(!keepalive.status(fixed).learned);
+    }
+
+    #[test]
+    fn drop_between_probes() {
+        let mut keepalive = KeepAlive::default();
+        keepalive.dropped(true);
+        assert_eq!(keepalive.interval(None), Duration::from_secs(40));
+        assert_eq!(keepalive.status(None).drops, 1);
+        assert_eq!(keepalive.status(None).last_probe, None);
+    }
+
+    #[test]
+    fn logout_is_not_a_drop() {
+        let mut keepalive = KeepAlive::default();
+        keepalive.logged_out();
+        keepalive.probed(false, true);
+        assert_eq!(keepalive.interval(None), DEFAULT_INTERVAL);
+        assert_eq!(keepalive.status(None).drops, 0);
+    }
+}
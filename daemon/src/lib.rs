@@ -1,16 +1,35 @@
+pub mod account;
 pub mod config;
 pub mod daemon;
+pub mod gateway;
+pub mod instance;
+pub mod keepalive;
+pub mod metrics;
+pub mod schedule;
 pub mod serve;
+mod supervisor;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod validate;
 
-use std::{error::Error as StdError, fmt::Display, io};
+use api::logout::LogoutError;
+use std::{convert::Infallible, error::Error as StdError, fmt::Display, io, time::Duration};
+
+use config::{AppConfig, AppState};
+use supervisor::Supervisor;
+use tokio::time;
 
 #[derive(Debug)]
 pub enum Error {
     SerdeJson(serde_json::Error),
     TokioIo(tokio::io::Error),
     StdIo(io::Error),
+    InvalidConfig(Vec<validate::ConfigIssue>),
+    Hyper(hyper::Error),
     #[cfg(feature = "auto-update")]
     FileNotify(notify::Error),
+    #[cfg(feature = "tls")]
+    Tls(tls::TlsError),
 }
 
 impl Display for Error {
@@ -21,27 +40,100 @@ impl Display for Error {
             #[cfg(feature = "auto-update")]
             Error::FileNotify(e) => write!(f, "FileNotify: {}", e),
             Error::StdIo(e) => write!(f, "StdIo: {}", e),
+            Error::Hyper(e) => write!(f, "Hyper: {}", e),
+            #[cfg(feature = "tls")]
+            Error::Tls(e) => write!(f, "Tls: {}", e),
+            Error::InvalidConfig(issues) => {
+                write!(f, "InvalidConfig:")?;
+                for issue in issues {
+                    write!(f, "\n  {}", issue)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 impl StdError for Error {}
 
-pub async fn start() {
-    let conf = daemon::start().await.unwrap();
-    let serv_handle = tokio::spawn(serve::Server::serve(conf.0));
+/// Time the daemon gets to stop after shutdown starts, windows kills a
+/// console process about 5 seconds after it was closed.
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(3);
+
+/// Run the daemon until shutdown, fails only when the config can't be read.
+pub async fn start() -> Result<(), Error> {
+    let conf = daemon::start().await?;
+    let shutdown = conf.read().await.shutdown_token();
+    let supervisor = Supervisor::new(conf.clone()).await;
+
+    let mut subsystems = vec![];
+    let login_conf = conf.clone();
+    subsystems.push(supervisor.spawn("login", move || {
+        let conf = login_conf.clone();
+        async move {
+            daemon::run(conf).await;
+            Ok::<_, Infallible>(())
+        }
+    }));
+    let schedule_conf = conf.clone();
+    subsystems.push(supervisor.spawn("schedule", move || {
+        let conf = schedule_conf.clone();
+        async move {
+            schedule::run(conf).await;
+            Ok::<_, Infallible>(())
+        }
+    }));
+    let account_conf = conf.clone();
+    subsystems.push(supervisor.spawn("account", move || {
+        let conf = account_conf.clone();
+        async move {
+            account::watch(conf).await;
+            Ok::<_, Infallible>(())
+        }
+    }));
+    let serv_conf = conf.clone();
+    subsystems.push(supervisor.spawn("server", move || serve::Server::serve(serv_conf.clone())));
+    // idle until gateway mode is turned on
+    let status_conf = conf.clone();
+    subsystems.push(supervisor.spawn("gateway status", move || {
+        gateway::serve_status(status_conf.clone())
+    }));
+    #[cfg(feature = "auto-update")]
+    {
+        let watcher_conf = conf.clone();
+        subsystems.push(supervisor.spawn("config watcher", move || {
+            let conf = watcher_conf.clone();
+            async move { conf.run_auto_update().await }
+        }));
+    }
 
-    // !todo pass panic to server thread
     tokio::select! {
-        conf_res = conf.1 => {
-            if let Err(e) = conf_res {
-                log::error!("conf watcher stopped: {:?}", e)
+        _ = daemon::shutdown_signal() => conf.write().await.stop(),
+        _ = shutdown.cancelled() => (),
+    }
+    log::info!("shutting down");
+
+    let stopped = time::timeout(SHUTDOWN_DEADLINE, async {
+        let logout = conf.read().await.config().logout_on_shutdown();
+        if logout {
+            match daemon::logout(&conf).await {
+                Ok(_) => log::info!("logged out before shutdown"),
+                Err(LogoutError::NotLoggedIn) => log::info!("not logged in at shutdown"),
+                Err(e) => log::warn!("logout before shutdown failed: {}", e),
             }
-        },
-        serv_res = serv_handle => {
-            if let Err(e) = serv_res {
-                log::error!("server stopped: {:?}", e)
+        }
+        for handle in subsystems {
+            if let Err(e) = handle.await {
+                log::error!("supervisor failed: {}", e);
             }
         }
+    })
+    .await;
+    if stopped.is_err() {
+        log::warn!(
+            "daemon didn't stop within {}s, exiting anyway",
+            SHUTDOWN_DEADLINE.as_secs()
+        );
     }
+    Ok(())
 }
@@ -0,0 +1,267 @@
+//! Prometheus metrics, rendered in the text exposition format by `GET /metrics`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use api::auth::{AuthError, Step};
+use lazy_static::lazy_static;
+
+use crate::config::unix_now;
+
+/// Upper bounds in seconds of the login step latency buckets
+const BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            if secs <= le {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    /// (outcome, error kind) -> count
+    logins: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+    online: AtomicBool,
+    steps: [Histogram; Step::ALL.len()],
+    config_reloads: AtomicU64,
+    /// (method, path, status) -> count
+    requests: Mutex<BTreeMap<(&'static str, &'static str, u16), u64>>,
+}
+
+/// `method` label of a request, clients can send any extension method.
+fn method_label(method: &str) -> &'static str {
+    match method {
+        "GET" => "GET",
+        "POST" => "POST",
+        "PUT" => "PUT",
+        "DELETE" => "DELETE",
+        _ => "other",
+    }
+}
+
+/// `kind` label of a failed login.
+fn error_kind(e: &AuthError) -> &'static str {
+    match e {
+        AuthError::ReqError(_) => "request",
+        AuthError::InvalidResponse(_) => "invalid_response",
+        AuthError::AuthFailed { .. } => "auth_failed",
+        AuthError::Authed => "already_online",
+    }
+}
+
+impl Metrics {
+    pub fn login_succeeded(&self) {
+        *self
+            .logins
+            .lock()
+            .unwrap()
+            .entry(("success", "none"))
+            .or_default() += 1;
+        self.set_online(true);
+    }
+
+    pub fn login_failed(&self, e: &AuthError) {
+        *self
+            .logins
+            .lock()
+            .unwrap()
+            .entry(("failure", error_kind(e)))
+            .or_default() += 1;
+        if !matches!(e, AuthError::Authed) {
+            self.set_online(false);
+        }
+    }
+
+    pub fn set_online(&self, online: bool) {
+        self.online.store(online, Ordering::Relaxed);
+    }
+
+    pub fn config_reloaded(&self) {
+        self.config_reloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `path` must be a known route to keep the label set bounded, unknown
+    /// methods are counted as `other`.
+    pub fn http_request(&self, method: &str, path: &'static str, status: u16) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method_label(method), path, status))
+            .or_default() += 1;
+    }
+
+    pub fn render(&self, last_login: Option<u64>) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "htu_net_login_attempts_total",
+            "counter",
+            "Login attempts by outcome and error kind.",
+        );
+        for ((outcome, kind), count) in self.logins.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "htu_net_login_attempts_total{{outcome=\"{}\",kind=\"{}\"}} {}",
+                outcome, kind, count
+            );
+        }
+
+        header(
+            &mut out,
+            "htu_net_online",
+            "gauge",
+            "Whether the campus network is usable.",
+        );
+        let _ = writeln!(
+            out,
+            "htu_net_online {}",
+            self.online.load(Ordering::Relaxed) as u8
+        );
+
+        if let Some(at) = last_login {
+            header(
+                &mut out,
+                "htu_net_seconds_since_last_login",
+                "gauge",
+                "Seconds since the last successful login.",
+            );
+            let _ = writeln!(
+                out,
+                "htu_net_seconds_since_last_login {}",
+                unix_now().saturating_sub(at)
+            );
+        }
+
+        header(
+            &mut out,
+            "htu_net_login_step_duration_seconds",
+            "histogram",
+            "Latency of the requests made while logging in.",
+        );
+        for (step, histogram) in Step::ALL.iter().zip(self.steps.iter()) {
+            let name = "htu_net_login_step_duration_seconds";
+            let step = step.name();
+            for (le, bucket) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{step=\"{}\",le=\"{}\"}} {}",
+                    name,
+                    step,
+                    le,
+                    bucket.load(Ordering::Relaxed)
+                );
+            }
+            let count = histogram.count.load(Ordering::Relaxed);
+            let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{step=\"{}\",le=\"+Inf\"}} {}",
+                name, step, count
+            );
+            let _ = writeln!(out, "{}_sum{{step=\"{}\"}} {}", name, step, sum);
+            let _ = writeln!(out, "{}_count{{step=\"{}\"}} {}", name, step, count);
+        }
+
+        header(
+            &mut out,
+            "htu_net_config_reloads_total",
+            "counter",
+            "Config file reloads.",
+        );
+        let _ = writeln!(
+            out,
+            "htu_net_config_reloads_total {}",
+            self.config_reloads.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "htu_net_http_requests_total",
+            "counter",
+            "Requests served by the daemon api.",
+        );
+        for ((method, path, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "htu_net_http_requests_total{{method=\"{}\",path=\"{}\",status=\"{}\"}} {}",
+                method, path, status, count
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Step observer registered with [`api::auth::observe_steps`].
+pub fn observe_step(step: Step, duration: Duration) {
+    let i = Step::ALL.iter().position(|s| *s == step).unwrap();
+    METRICS.steps[i].observe(duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use api::auth::{AuthError, Step};
+
+    use super::Metrics;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.login_succeeded();
+        metrics.login_failed(&AuthError::AuthFailed { msg: "".into() });
+        metrics.steps[3].observe(Duration::from_millis(300));
+        metrics.http_request("GET", "/api/v1/status", 200);
+        metrics.http_request("BREW", "/api/v1/status", 405);
+        let out = metrics.render(None);
+
+        assert!(out.contains("htu_net_login_attempts_total{outcome=\"success\",kind=\"none\"} 1"));
+        assert!(out
+            .contains("htu_net_login_attempts_total{outcome=\"failure\",kind=\"auth_failed\"} 1"));
+        assert!(out.contains("htu_net_online 0"));
+        assert!(!out.contains("htu_net_seconds_since_last_login"));
+        let step = Step::QuickAuth.name();
+        assert!(out.contains(&format!("_bucket{{step=\"{}\",le=\"0.25\"}} 0", step)));
+        assert!(out.contains(&format!("_bucket{{step=\"{}\",le=\"0.5\"}} 1", step)));
+        assert!(out.contains(&format!("_sum{{step=\"{}\"}} 0.3", step)));
+        assert!(out.contains(
+            "htu_net_http_requests_total{method=\"GET\",path=\"/api/v1/status\",status=\"200\"} 1"
+        ));
+        assert!(out.contains(
+            "htu_net_http_requests_total{method=\"other\",path=\"/api/v1/status\",status=\"405\"} 1"
+        ));
+    }
+}
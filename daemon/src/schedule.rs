@@ -0,0 +1,189 @@
+//! Login windows: outside of them the daemon doesn't log in by itself, and
+//! optionally logs out when one ends.
+
+use std::time::Duration;
+
+use api::logout::LogoutError;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveDateTime, TimeZone};
+use proto::ScheduleStatus;
+use tokio::time;
+
+use crate::{
+    config::{AppConfig, AppState, GlobalAppInfo, ScheduleConfig},
+    daemon,
+};
+
+/// longest wait before looking at the schedule again, so that config changes
+/// and clock adjustments apply
+const RECHECK: Duration = Duration::from_secs(60);
+
+/// Start and end of the windows opening on `day`.
+fn opened(conf: &ScheduleConfig, day: NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    conf.windows()
+        .iter()
+        .filter(|window| window.opens_on(day.weekday()))
+        .filter_map(|window| {
+            let (start, end) = (window.start()?, window.end()?);
+            let end_day = if end > start { day } else { day.succ_opt()? };
+            Some((day.and_time(start), end_day.and_time(end)))
+        })
+        .collect()
+}
+
+/// Whether auto-login is allowed at the local time `now`.
+pub fn allowed(conf: &ScheduleConfig, now: NaiveDateTime) -> bool {
+    if conf.windows().is_empty() {
+        return true;
+    }
+    let today = now.date();
+    [today.pred_opt(), Some(today)]
+        .into_iter()
+        .flatten()
+        .flat_map(|day| opened(conf, day))
+        .any(|(start, end)| start <= now && now < end)
+}
+
+/// Local time `allowed` changes at next, looking a week ahead.
+pub fn next_change(conf: &ScheduleConfig, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let allowed_now = allowed(conf, now);
+    let yesterday = now.date().pred_opt()?;
+    let mut edges: Vec<_> = (0..=8)
+        .filter_map(|days| yesterday.checked_add_days(Days::new(days)))
+        .flat_map(|day| opened(conf, day))
+        .flat_map(|(start, end)| [start, end])
+        .filter(|edge| *edge > now)
+        .collect();
+    edges.sort();
+    edges
+        .into_iter()
+        .find(|edge| allowed(conf, *edge) != allowed_now)
+}
+
+/// None when there are no windows to report on.
+pub fn status<Tz: TimeZone>(conf: &ScheduleConfig, now: DateTime<Tz>) -> Option<ScheduleStatus> {
+    if conf.windows().is_empty() {
+        return None;
+    }
+    let local = now.naive_local();
+    Some(ScheduleStatus {
+        allowed: allowed(conf, local),
+        next_change: next_change(conf, local).and_then(|at| {
+            let at = now.timezone().from_local_datetime(&at).earliest()?;
+            u64::try_from(at.timestamp()).ok()
+        }),
+    })
+}
+
+fn wait(conf: &ScheduleConfig, now: NaiveDateTime) -> Duration {
+    next_change(conf, now)
+        .and_then(|at| (at - now).to_std().ok())
+        .map_or(RECHECK, |wait| wait.min(RECHECK))
+}
+
+/// How long to hold off auto-login for, none inside a login window.
+pub(crate) async fn paused(appinfo: &GlobalAppInfo) -> Option<Duration> {
+    let conf = appinfo.read().await.config().schedule().cloned()?;
+    let now = Local::now().naive_local();
+    (!allowed(&conf, now)).then(|| wait(&conf, now))
+}
+
+/// Follow the schedule until shutdown, logging out when a window ends if
+/// configured to.
+pub async fn run(appinfo: GlobalAppInfo) {
+    let shutdown = appinfo.read().await.shutdown_token();
+    let mut was_allowed = None;
+    loop {
+        let conf = appinfo.read().await.config().schedule().cloned();
+        let now = Local::now().naive_local();
+        let open = conf.as_ref().is_none_or(|conf| allowed(conf, now));
+        if was_allowed.is_some_and(|was| was != open) {
+            let logout = conf.as_ref().is_some_and(ScheduleConfig::logout_at_end);
+            transition(&appinfo, open, logout).await;
+        }
+        was_allowed = Some(open);
+        let wait = conf.map_or(RECHECK, |conf| wait(&conf, now));
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = time::sleep(wait) => (),
+        }
+    }
+}
+
+async fn transition(appinfo: &GlobalAppInfo, open: bool, logout: bool) {
+    if open {
+        log::info!("login window opened, auto-login resumed");
+        return;
+    }
+    log::info!("login window closed, auto-login paused");
+    if !logout {
+        #[cfg(feature = "sys-notify")]
+        daemon::notify("登录时段已结束，暂停自动登录").await;
+        return;
+    }
+    match daemon::logout(appinfo).await {
+        Ok(()) => {
+            log::info!("logged out at the end of the login window");
+            #[cfg(feature = "sys-notify")]
+            daemon::notify("登录时段已结束，已登出校园网").await;
+        }
+        Err(LogoutError::NotLoggedIn) => log::info!("not logged in at the end of the login window"),
+        Err(e) => log::warn!("logout at the end of the login window failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+
+    use crate::config::ScheduleConfig;
+
+    use super::{allowed, next_change, status};
+
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        // 2024-01-01 is a monday
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_time(crate::config::parse_time(time).unwrap())
+    }
+
+    fn schedule(json: &str) -> ScheduleConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn overnight_window() {
+        let conf =
+            schedule(r#"{"windows": [{"days": ["fri"], "start": "22:00", "end": "02:00"}]}"#);
+        assert!(!allowed(&conf, at(5, "21:59")));
+        assert!(allowed(&conf, at(5, "22:00")));
+        assert!(allowed(&conf, at(6, "01:59")));
+        assert!(!allowed(&conf, at(6, "02:00")));
+        assert_eq!(next_change(&conf, at(6, "01:00")), Some(at(6, "02:00")));
+        assert_eq!(next_change(&conf, at(6, "02:00")), Some(at(12, "22:00")));
+    }
+
+    #[test]
+    fn adjacent_windows_are_one() {
+        let conf = schedule(
+            r#"{"windows": [
+                {"start": "08:00", "end": "12:00"},
+                {"days": ["mon", "Tue"], "start": "12:00", "end": "23:30"}
+            ]}"#,
+        );
+        assert!(allowed(&conf, at(1, "12:00")));
+        assert_eq!(next_change(&conf, at(1, "09:00")), Some(at(1, "23:30")));
+        assert_eq!(next_change(&conf, at(3, "09:00")), Some(at(3, "12:00")));
+        assert_eq!(next_change(&conf, at(3, "13:00")), Some(at(4, "08:00")));
+    }
+
+    #[test]
+    fn reports_unix_time() {
+        let conf = schedule(r#"{"windows": [{"start": "08:00", "end": "00:00"}]}"#);
+        let tz = FixedOffset::east_opt(8 * 3600).unwrap();
+        let now = tz.from_local_datetime(&at(1, "07:00")).unwrap();
+        let report = status(&conf, now).unwrap();
+        assert!(!report.allowed);
+        assert_eq!(report.next_change, Some(now.timestamp() as u64 + 3600));
+        assert!(status(&schedule(r#"{"windows": []}"#), now).is_none());
+    }
+}
@@ -1,190 +1,608 @@
-use std::{collections::HashMap, convert::Infallible, time::Duration};
+use std::{
+    convert::Infallible,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+};
 
-use api::{auth::UserInfo, logout::logout_async};
-use hyper::{body::HttpBody, Body, Method, Request, Response};
+use hyper::{
+    body::HttpBody,
+    header,
+    server::{accept::Accept, conn::AddrIncoming, conn::AddrStream},
+    Body, Method, Request, Response,
+};
+use proto::{
+    path, ApiError, Discovery, Envelope, ErrorCode, KickRequest, UserResponse, DISCOVERY_FILE,
+};
+use serde::Serialize;
 use tokio::{
-    runtime::Handle,
-    task::{self, LocalSet},
-    time,
+    fs,
+    io::{self, AsyncRead, AsyncWrite},
+    sync::broadcast::error::RecvError,
 };
 
 use crate::{
-    config::{AppConfig, AppState, GlobalAppInfo},
-    daemon::login_net,
+    account,
+    config::{config_dir, AppConfig, AppState, Config, GlobalAppInfo},
+    daemon::{self, login_net},
+    gateway,
+    metrics::METRICS,
+    Error,
 };
 
+/// OpenAPI description of the routes below
+const OPENAPI: &str = include_str!("openapi.json");
+
+/// Static files of the web dashboard, compiled into the binary
+const ASSETS: [(&str, &str, &str); 3] = [
+    (
+        "/",
+        "text/html; charset=utf-8",
+        include_str!("dashboard/index.html"),
+    ),
+    (
+        "/app.js",
+        "text/javascript; charset=utf-8",
+        include_str!("dashboard/app.js"),
+    ),
+    (
+        "/style.css",
+        "text/css; charset=utf-8",
+        include_str!("dashboard/style.css"),
+    ),
+];
+
+const ROUTES: [&str; 12] = [
+    path::PING,
+    path::USER,
+    path::STATUS,
+    path::HISTORY,
+    path::EVENTS,
+    path::LOGIN,
+    path::LOGOUT,
+    path::ACCOUNT,
+    path::SESSIONS,
+    path::SHUTDOWN,
+    path::OPENAPI,
+    path::METRICS,
+];
+
 pub struct Server;
 
-pub(crate) struct JsonResponse;
+/// A connection accepted by the server, plain or tls.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    fn remote_addr(&self) -> SocketAddr;
+}
 
-impl JsonResponse {
-    pub(crate) fn crate_response_map(msg: &str) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        map.insert("msg".to_string(), msg.to_string());
-        map
+impl Connection for AddrStream {
+    fn remote_addr(&self) -> SocketAddr {
+        AddrStream::remote_addr(self)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Connection for tokio_rustls::server::TlsStream<tokio::net::TcpStream> {
+    fn remote_addr(&self) -> SocketAddr {
+        // an unknown peer isn't loopback, so it has to authenticate
+        self.get_ref()
+            .0
+            .peer_addr()
+            .unwrap_or_else(|_| (Ipv4Addr::UNSPECIFIED, 0).into())
     }
+}
+
+pub(crate) struct JsonResponse;
 
-    pub(crate) fn create_response(status: u16, msg: &str) -> HttpResponse {
+impl JsonResponse {
+    fn json<T: Serialize>(status: u16, body: &T) -> HttpResponse {
         Response::builder()
             .status(status)
             .header("Content-Type", "application/json")
-            .body(Body::from(
-                serde_json::to_string(&Self::crate_response_map(msg)).unwrap(),
-            ))
+            .body(Body::from(serde_json::to_string(body).unwrap()))
+    }
+
+    pub(crate) fn data<T: Serialize>(data: T) -> HttpResponse {
+        Self::json(200, &Envelope::data(data))
+    }
+
+    pub(crate) fn ok() -> HttpResponse {
+        Self::json(200, &Envelope::empty())
     }
 
-    pub(crate) fn ok(msg: &str) -> HttpResponse {
-        Self::create_response(200, msg)
+    /// Error response, the status code is derived from the error code
+    pub(crate) fn error(error: ApiError) -> HttpResponse {
+        Self::json(error.code.status(), &Envelope::<()>::error(error))
     }
 
-    pub(crate) fn bad_request(msg: &str) -> HttpResponse {
-        Self::create_response(400, msg)
+    pub(crate) fn error_msg(code: ErrorCode, msg: impl Into<String>) -> HttpResponse {
+        Self::error(ApiError::new(code, msg))
     }
 }
 
 type HttpResponse = Result<Response<Body>, hyper::http::Error>;
 type HttpRequest = Request<Body>;
 impl Server {
-    pub async fn serve(conf: GlobalAppInfo) -> Result<(), hyper::Error> {
-        let addr = ([127, 0, 0, 1], 11451).into();
+    pub async fn serve(conf: GlobalAppInfo) -> Result<(), Error> {
+        let (addr, tls) = {
+            let conf = conf.read().await;
+            (conf.config().listen_addr(), conf.config().tls())
+        };
+        if tls {
+            #[cfg(feature = "tls")]
+            return Self::serve_tls(conf, addr).await;
+            #[cfg(not(feature = "tls"))]
+            log::warn!("tls is enabled but this build doesn't support it, serving plain http");
+        }
+        let incoming = AddrIncoming::bind(&addr).map_err(Error::Hyper)?;
+        let local_addr = incoming.local_addr();
+        Self::run(conf, incoming, local_addr, None).await
+    }
+
+    #[cfg(feature = "tls")]
+    async fn serve_tls(conf: GlobalAppInfo, addr: SocketAddr) -> Result<(), Error> {
+        use crate::tls::{self, Identity, TLS_DIR};
+
+        let identity =
+            Identity::load_or_generate(&config_dir().unwrap().join(TLS_DIR)).map_err(Error::Tls)?;
+        let fingerprint = identity.fingerprint();
+        log::info!(
+            "serving over https, certificate fingerprint {}",
+            fingerprint
+        );
+        let acceptor = identity.acceptor().map_err(Error::Tls)?;
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(Error::TokioIo)?;
+        let local_addr = listener.local_addr().map_err(Error::TokioIo)?;
+        let incoming = tls::incoming(listener, acceptor);
+        Self::run(conf, incoming, local_addr, Some(fingerprint)).await
+    }
+
+    /// Serve connections from `incoming` until shutdown, `fingerprint` is
+    /// set when they are tls connections.
+    async fn run<I>(
+        conf: GlobalAppInfo,
+        incoming: I,
+        local_addr: SocketAddr,
+        fingerprint: Option<String>,
+    ) -> Result<(), Error>
+    where
+        I: Accept,
+        I::Conn: Connection,
+        I::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
         let conf_inner = conf.clone();
-        let make_svc =
-            hyper::service::make_service_fn(move |conn: &hyper::server::conn::AddrStream| {
-                let conf = conf_inner.clone();
-                let remote_addr = conn.remote_addr();
-                async move {
-                    Ok::<_, hyper::Error>(hyper::service::service_fn(move |req| {
-                        if req.uri().path() != "/" {
-                            log::info!(
-                                "{} {} from {}",
-                                req.method(),
-                                req.uri().path(),
-                                remote_addr
-                            );
-                        }
-                        Self::router(req, conf.clone())
-                    }))
-                }
-            });
-        let server = hyper::Server::bind(&addr).serve(make_svc);
-        server
+        let make_svc = hyper::service::make_service_fn(move |conn: &I::Conn| {
+            let conf = conf_inner.clone();
+            let remote_addr = conn.remote_addr();
+            async move {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(move |req| {
+                    // clients poll these, logging each poll would flood the log
+                    let polled = req.method() == Method::GET
+                        && [path::PING, path::STATUS, path::HISTORY].contains(&req.uri().path());
+                    if polled {
+                        log::debug!("{} {} from {}", req.method(), req.uri().path(), remote_addr);
+                    } else {
+                        log::info!("{} {} from {}", req.method(), req.uri().path(), remote_addr);
+                    }
+                    Self::router(req, remote_addr, conf.clone())
+                }))
+            }
+        });
+        let server = hyper::Server::builder(incoming).serve(make_svc);
+        log::info!("listening on {}", local_addr);
+        conf.write().await.set_tls_fingerprint(fingerprint.clone());
+        let discovery = config_dir().unwrap().join(DISCOVERY_FILE);
+        Self::publish(&discovery, local_addr, fingerprint)
+            .await
+            .map_err(Error::TokioIo)?;
+
+        let shutdown = conf.read().await.shutdown_token();
+        let res = server
             .with_graceful_shutdown(async move {
                 log::info!("server thread running");
-                while conf.running().await {
-                    time::sleep(Duration::from_millis(250)).await;
-                }
+                shutdown.cancelled().await;
                 log::info!("server thread exit");
             })
-            .await
+            .await;
+        let _ = fs::remove_file(&discovery).await;
+        res.map_err(Error::Hyper)
     }
 
-    async fn router(req: HttpRequest, conf: GlobalAppInfo) -> Result<Response<Body>, Infallible> {
+    /// Tell clients where to find the api, the port may have been picked by
+    /// the OS.
+    async fn publish(
+        path: &Path,
+        mut addr: SocketAddr,
+        fingerprint: Option<String>,
+    ) -> io::Result<()> {
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let discovery = Discovery {
+            addr: match fingerprint {
+                Some(_) => format!("https://{}", addr),
+                None => addr.to_string(),
+            },
+            pid: std::process::id(),
+            fingerprint,
+        };
+        fs::write(path, serde_json::to_vec(&discovery).unwrap()).await
+    }
+
+    async fn router(
+        req: HttpRequest,
+        remote_addr: SocketAddr,
+        conf: GlobalAppInfo,
+    ) -> Result<Response<Body>, Infallible> {
         let path = req.uri().clone();
-        match Self::routes(req, conf).await {
-            Ok(res) => Ok(res),
+        let method = req.method().clone();
+        let res = match Self::routes(req, remote_addr, conf).await {
+            Ok(res) => res,
             Err(e) => {
                 log::error!("error while processing {}: {}", path, e);
-                Ok(JsonResponse::bad_request("Internal Server Error").unwrap())
+                JsonResponse::error_msg(ErrorCode::Internal, "Internal Server Error").unwrap()
             }
-        }
+        };
+        METRICS.http_request(
+            method.as_str(),
+            Self::route_label(path.path()),
+            res.status().as_u16(),
+        );
+        Ok(res)
     }
 
-    async fn routes(req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+    /// Metric label of a request path, unknown paths share one label.
+    fn route_label(path: &str) -> &'static str {
+        ROUTES
+            .iter()
+            .chain(ASSETS.iter().map(|(path, ..)| path))
+            .find(|route| **route == path)
+            .copied()
+            .unwrap_or("other")
+    }
+
+    async fn routes(
+        req: HttpRequest,
+        remote_addr: SocketAddr,
+        conf: GlobalAppInfo,
+    ) -> HttpResponse {
+        if !Self::same_origin(&req) {
+            log::warn!("rejected cross origin request to {}", req.uri().path());
+            return JsonResponse::error_msg(ErrorCode::Forbidden, "Cross Origin Request");
+        }
+        // the dashboard itself is public, it asks for the token
+        let asset = req.method() == Method::GET && Self::asset(req.uri().path()).is_some();
+        let token = conf.read().await.config().api_token().map(str::to_string);
+        if !asset && !Self::authorized(&req, remote_addr, token.as_deref()) {
+            log::warn!("rejected unauthorized request from {}", remote_addr);
+            return JsonResponse::error_msg(ErrorCode::Unauthorized, "Unauthorized");
+        }
         match (req.method(), req.uri().path()) {
-            (&Method::GET, "/") => Self::handle_index(req, conf).await,
-            (&Method::GET, "/user") => Self::handle_get_user_info(req, conf).await,
-            (&Method::POST, "/user") => Self::handle_set_user_info(req, conf).await,
-            (&Method::GET, "/login") => Self::handle_login(req, conf).await,
-            (&Method::GET, "/exit") => Self::handle_exit(req, conf).await,
-            (&Method::GET, "/logout") => Self::handle_logout(req, conf).await,
+            (&Method::GET, path::PING) => Self::handle_index(req, conf).await,
+            (&Method::GET, path::USER) => Self::handle_get_user_info(req, conf).await,
+            (&Method::POST, path::USER) => Self::handle_set_user_info(req, conf).await,
+            (&Method::GET, path::STATUS) => Self::handle_status(req, conf).await,
+            (&Method::GET, path::HISTORY) => Self::handle_history(req, conf).await,
+            (&Method::GET, path::EVENTS) => Self::handle_events(req, conf).await,
+            (&Method::POST, path::LOGIN) => Self::handle_login(req, conf).await,
+            (&Method::POST, path::LOGOUT) => Self::handle_logout(req, conf).await,
+            (&Method::GET, path::ACCOUNT) => Self::handle_account(req, conf).await,
+            (&Method::GET, path::SESSIONS) => Self::handle_sessions(req, conf).await,
+            (&Method::POST, path::SESSIONS) => Self::handle_kick(req, conf).await,
+            (&Method::POST, path::SHUTDOWN) => Self::handle_exit(req, conf).await,
+            (&Method::GET, path::OPENAPI) => Self::handle_openapi(req, conf).await,
+            (&Method::GET, path::METRICS) => Self::handle_metrics(req, conf).await,
+            (_, p) if ROUTES.contains(&p) => {
+                JsonResponse::error_msg(ErrorCode::MethodNotAllowed, "Method Not Allowed")
+            }
+            (&Method::GET, p) => match Self::asset(p) {
+                Some((_, content_type, content)) => Response::builder()
+                    .header("Content-Type", *content_type)
+                    .body(Body::from(*content)),
+                None => Self::handle_not_found(req, conf).await,
+            },
             _ => Self::handle_not_found(req, conf).await,
         }
     }
 
+    fn asset(path: &str) -> Option<&'static (&'static str, &'static str, &'static str)> {
+        ASSETS.iter().find(|(p, ..)| *p == path)
+    }
+
+    /// Requests from this machine are trusted, others need the api token.
+    fn authorized(req: &HttpRequest, remote_addr: SocketAddr, token: Option<&str>) -> bool {
+        if remote_addr.ip().is_loopback() {
+            return true;
+        }
+        let Some(token) = token else {
+            return false;
+        };
+        let bearer = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+        // compare in constant time, don't leak the token through timing
+        bearer.is_some_and(|bearer| {
+            bearer.len() == token.len()
+                && bearer
+                    .bytes()
+                    .zip(token.bytes())
+                    .fold(0, |diff, (a, b)| diff | (a ^ b))
+                    == 0
+        })
+    }
+
+    /// Browsers send `Origin` with cross site requests, only the dashboard
+    /// served from this host may use the api. Other clients don't send it.
+    fn same_origin(req: &HttpRequest) -> bool {
+        let Some(origin) = req.headers().get(header::ORIGIN) else {
+            return true;
+        };
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .and_then(|h| h.to_str().ok());
+        let origin = origin
+            .to_str()
+            .ok()
+            .and_then(|o| o.split_once("://"))
+            .map(|(_, host)| host);
+        matches!((origin, host), (Some(origin), Some(host)) if origin == host)
+    }
+
     async fn handle_index(_req: Request<Body>, _conf: GlobalAppInfo) -> HttpResponse {
-        JsonResponse::ok("hello from htu-net daemon")
+        JsonResponse::data("hello from htu-net daemon")
     }
 
     async fn handle_not_found(_req: HttpRequest, _conf: GlobalAppInfo) -> HttpResponse {
+        JsonResponse::error_msg(ErrorCode::NotFound, "Not Found")
+    }
+
+    async fn handle_openapi(_req: HttpRequest, _conf: GlobalAppInfo) -> HttpResponse {
         Response::builder()
-            .status(404)
-            .body(Body::from("Not Found"))
+            .header("Content-Type", "application/json")
+            .body(Body::from(OPENAPI))
+    }
+
+    async fn handle_metrics(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        let last_login = conf.read().await.status().last_login;
+        Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(METRICS.render(last_login)))
     }
 
     async fn handle_get_user_info(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
-        Ok(Response::new(Body::from({
-            let json = serde_json::to_string(&conf.read().await.config().user()).unwrap();
-            if json == "null" {
-                "{}".to_string()
-            } else {
-                json
-            }
-        })))
+        let user: UserResponse = conf.read().await.config().user().cloned();
+        JsonResponse::data(user)
+    }
+
+    async fn handle_status(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        JsonResponse::data(conf.read().await.status())
+    }
+
+    async fn handle_history(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        JsonResponse::data(conf.read().await.history())
     }
 
     async fn handle_set_user_info(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
         let body = match _req.collect().await {
             Ok(b) => b.to_bytes(),
-            Err(e) => return JsonResponse::bad_request(&format!("Error reading body: {}", e)),
+            Err(e) => {
+                return JsonResponse::error_msg(
+                    ErrorCode::InvalidRequest,
+                    format!("Error reading body: {}", e),
+                )
+            }
         };
-        let user: UserInfo = match serde_json::from_slice(&body) {
+        let user = match Config::check_user(&body) {
             Ok(info) => info,
-            Err(e) => return JsonResponse::bad_request(&format!("Error parsing body: {}", e)),
+            Err(issues) => return JsonResponse::error(ApiError::invalid(issues)),
         };
         conf.write().await.config_mut().set_user(user);
         if let Err(e) = conf.read().await.save().await {
-            JsonResponse::bad_request(&format!("Error saving conf: {}", e))
+            JsonResponse::error_msg(ErrorCode::Internal, format!("Error saving conf: {}", e))
         } else {
             log::info!("user info updated");
-            JsonResponse::ok("success")
+            JsonResponse::ok()
         }
     }
 
     async fn handle_login(_req: HttpRequest, app_conf: GlobalAppInfo) -> HttpResponse {
-        task::spawn_blocking(move || {
-            let handle = Handle::current();
-            handle.block_on(async move {
-                let local = LocalSet::new();
-                local
-                    .run_until(async move {
-                        task::spawn_local(async move {
-                            if let Some(user) = app_conf.read().await.config().user() {
-                                if let Err(e) = login_net(user).await {
-                                    JsonResponse::bad_request(&format!("Login error: {}", e))
-                                } else {
-                                    JsonResponse::ok("success")
-                                }
-                            } else {
-                                JsonResponse::bad_request("user info not set")
-                            }
-                        })
-                        .await
-                    })
-                    .await
-            })
-        })
-        .await
-        .unwrap()
-        .unwrap()
+        let user = app_conf.read().await.config().user().cloned();
+        let Some(user) = user else {
+            return JsonResponse::error_msg(ErrorCode::UserNotSet, "user info not set");
+        };
+        let lock = app_conf.read().await.login_lock();
+        let Ok(_flight) = lock.try_lock_owned() else {
+            return JsonResponse::error_msg(
+                ErrorCode::LoginInProgress,
+                "another login is in progress",
+            );
+        };
+        // an explicit retry, auto-login resumes unless refused again
+        let held = app_conf.read().await.config().login_hold().is_some();
+        app_conf.write().await.resume_login();
+        if held {
+            if let Err(e) = app_conf.read().await.save().await {
+                log::warn!("failed to save the lifted login hold: {}", e);
+            }
+        }
+        let network = gateway::network(&app_conf).await;
+        match login_net(&app_conf, &network, &user).await {
+            Ok(session) => {
+                daemon::logged_in(&app_conf, session).await;
+                JsonResponse::ok()
+            }
+            Err(e) => {
+                app_conf.write().await.login_failed(&e);
+                daemon::hold_if_permanent(&app_conf, &user, &e).await;
+                JsonResponse::error(ApiError::from(&e))
+            }
+        }
     }
 
     async fn handle_exit(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
         conf.write().await.stop();
-        JsonResponse::ok("success")
+        JsonResponse::ok()
     }
 
     async fn handle_logout(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
-        match logout_async(
-            conf.read()
-                .await
-                .config()
-                .logout_url_base()
-                .unwrap_or("http://10.101.2.205"),
-        )
-        .await
-        {
-            Ok(_) => JsonResponse::ok("success"),
-            Err(e) => JsonResponse::bad_request(&format!("Error logging out: {}", e)),
+        match daemon::logout(&conf).await {
+            Ok(_) => JsonResponse::ok(),
+            Err(e) => JsonResponse::error(ApiError::from(&e)),
+        }
+    }
+
+    async fn handle_account(req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        let refresh = url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+            .any(|(key, value)| key == "refresh" && (value == "true" || value == "1"));
+        match account::get(&conf, refresh).await {
+            Ok(account) => JsonResponse::data(account),
+            Err(e) => JsonResponse::error(ApiError::from(&e)),
+        }
+    }
+
+    async fn handle_sessions(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        let user = conf.read().await.config().user().cloned();
+        let Some(user) = user else {
+            return JsonResponse::error_msg(ErrorCode::UserNotSet, "user info not set");
+        };
+        match account::sessions(&conf, &user).await {
+            Ok(sessions) => JsonResponse::data(sessions),
+            Err(e) => JsonResponse::error(ApiError::from(&e)),
+        }
+    }
+
+    async fn handle_kick(req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        let body = match req.collect().await {
+            Ok(b) => b.to_bytes(),
+            Err(e) => {
+                return JsonResponse::error_msg(
+                    ErrorCode::InvalidRequest,
+                    format!("Error reading body: {}", e),
+                )
+            }
+        };
+        let kick: KickRequest = match serde_json::from_slice(&body) {
+            Ok(kick) => kick,
+            Err(e) => return JsonResponse::error_msg(ErrorCode::InvalidRequest, e.to_string()),
+        };
+        let user = conf.read().await.config().user().cloned();
+        let Some(user) = user else {
+            return JsonResponse::error_msg(ErrorCode::UserNotSet, "user info not set");
+        };
+        // the ip is only for logs, ask for it rather than trusting the client
+        let ip = match account::sessions(&conf, &user).await {
+            Ok(sessions) => sessions
+                .into_iter()
+                .find(|device| device.session.as_ref() == Some(&kick.session))
+                .map(|device| device.ip),
+            Err(e) => return JsonResponse::error(ApiError::from(&e)),
+        };
+        let Some(ip) = ip else {
+            return JsonResponse::error_msg(ErrorCode::NotFound, "no such session");
+        };
+        match account::kick(&conf, &user, &kick.session, &ip, false).await {
+            Ok(()) => JsonResponse::ok(),
+            Err(e) => JsonResponse::error(ApiError::from(&e)),
         }
     }
+
+    /// Stream events as newline delimited json until the client goes away
+    /// or the daemon stops.
+    async fn handle_events(_req: HttpRequest, conf: GlobalAppInfo) -> HttpResponse {
+        let mut rx = conf.read().await.subscribe();
+        let shutdown = conf.read().await.shutdown_token();
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = rx.recv() => event,
+                    // end the stream so that the server can shut down
+                    _ = shutdown.cancelled() => break,
+                };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        log::warn!("event subscriber lagged behind by {} events", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let mut line = serde_json::to_vec(&event).unwrap();
+                line.push(b'\n');
+                if sender.send_data(line.into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Response::builder()
+            .header("Content-Type", "application/x-ndjson")
+            .body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header, Body, Request};
+
+    use super::{Server, OPENAPI, ROUTES};
+
+    #[test]
+    fn openapi_documents_every_route() {
+        let doc: serde_json::Value = serde_json::from_str(OPENAPI).unwrap();
+        for route in ROUTES {
+            assert!(
+                doc["paths"].get(route).is_some(),
+                "{} not documented",
+                route
+            );
+        }
+    }
+
+    #[test]
+    fn same_origin() {
+        let req = |origin: Option<&str>| {
+            let mut req = Request::post("/api/v1/login").header(header::HOST, "127.0.0.1:11451");
+            if let Some(origin) = origin {
+                req = req.header(header::ORIGIN, origin);
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        assert!(Server::same_origin(&req(None)));
+        assert!(Server::same_origin(&req(Some("http://127.0.0.1:11451"))));
+        assert!(!Server::same_origin(&req(Some("https://evil.example"))));
+        assert!(!Server::same_origin(&req(Some("null"))));
+    }
+
+    #[test]
+    fn authorized() {
+        let req = |auth: Option<&str>| {
+            let mut req = Request::get("/api/v1/status");
+            if let Some(auth) = auth {
+                req = req.header(header::AUTHORIZATION, auth);
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        let local = "127.0.0.1:50000".parse().unwrap();
+        let lan = "192.168.1.20:50000".parse().unwrap();
+        let token = Some("0123456789abcdef");
+        assert!(Server::authorized(&req(None), local, None));
+        assert!(!Server::authorized(&req(None), lan, None));
+        assert!(!Server::authorized(&req(None), lan, token));
+        assert!(!Server::authorized(
+            &req(Some("Bearer 0123456789abcdeX")),
+            lan,
+            token
+        ));
+        assert!(Server::authorized(
+            &req(Some("Bearer 0123456789abcdef")),
+            lan,
+            token
+        ));
+    }
 }
@@ -0,0 +1,139 @@
+//! Keeps the daemon's subsystems running until shutdown.
+
+use std::{fmt::Display, future::Future, time::Duration};
+
+use tokio::{
+    task::{JoinError, JoinHandle},
+    time::{self, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{AppState, GlobalAppInfo};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// a subsystem that ran this long before crashing is restarted right away
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+pub(crate) struct Supervisor {
+    conf: GlobalAppInfo,
+    shutdown: CancellationToken,
+    initial_backoff: Duration,
+}
+
+impl Supervisor {
+    pub(crate) async fn new(conf: GlobalAppInfo) -> Self {
+        let shutdown = conf.read().await.shutdown_token();
+        Self {
+            conf,
+            shutdown,
+            initial_backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Run the subsystem created by `start` until shutdown. Whenever it
+    /// panics, fails or returns early, the crash is recorded and a new one is
+    /// started after an exponential backoff.
+    pub(crate) fn spawn<F, Fut, E>(&self, name: &'static str, start: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let conf = self.conf.clone();
+        let shutdown = self.shutdown.clone();
+        let initial_backoff = self.initial_backoff;
+        tokio::spawn(async move {
+            let mut backoff = initial_backoff;
+            loop {
+                let started = Instant::now();
+                let res = tokio::spawn(start()).await;
+                if shutdown.is_cancelled() {
+                    break;
+                }
+                let reason = match res {
+                    Ok(Ok(())) => "stopped unexpectedly".to_string(),
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) => join_error(e),
+                };
+                if started.elapsed() > STABLE_AFTER {
+                    backoff = initial_backoff;
+                }
+                log::error!("{} crashed: {}, restarting in {:?}", name, reason, backoff);
+                conf.write().await.subsystem_crashed(name, reason);
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = time::sleep(backoff) => (),
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            log::info!("{} stopped", name);
+        })
+    }
+}
+
+fn join_error(e: JoinError) -> String {
+    if !e.is_panic() {
+        return e.to_string();
+    }
+    let panic = e.into_panic();
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        format!("panicked: {}", msg)
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        format!("panicked: {}", msg)
+    } else {
+        "panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::config::{AppConfig, AppInfo, AppState, Config};
+
+    use super::Supervisor;
+
+    #[tokio::test]
+    async fn restarts_after_panic() {
+        let conf = AppInfo::new(Config::default(), PathBuf::new()).global();
+        let mut supervisor = Supervisor::new(conf.clone()).await;
+        supervisor.initial_backoff = Duration::from_millis(1);
+
+        let runs = Arc::new(AtomicU32::new(0));
+        let runs_inner = runs.clone();
+        let shutdown = supervisor.shutdown.clone();
+        let handle = supervisor.spawn("test", move || {
+            let runs = runs_inner.clone();
+            let shutdown = shutdown.clone();
+            async move {
+                match runs.fetch_add(1, Ordering::SeqCst) {
+                    0 => panic!("boom"),
+                    1 => Err("failed"),
+                    _ => {
+                        shutdown.cancelled().await;
+                        Ok(())
+                    }
+                }
+            }
+        });
+        while runs.load(Ordering::SeqCst) < 3 {
+            tokio::task::yield_now().await;
+        }
+        conf.write().await.stop();
+        handle.await.unwrap();
+
+        let crashes = conf.read().await.status().crashes;
+        assert_eq!(crashes.len(), 2);
+        assert_eq!(crashes[0].subsystem, "test");
+        assert_eq!(crashes[0].reason, "panicked: boom");
+        assert_eq!(crashes[1].reason, "failed");
+    }
+}
@@ -0,0 +1,161 @@
+//! Serves the api over https with a self-signed certificate. Clients can't
+//! verify it against a CA, they pin its fingerprint instead.
+
+use std::{error::Error, fmt::Display, fs, io, path::Path, sync::Arc, time::Duration};
+
+use hyper::server::accept::{self, Accept};
+use ring::digest::{digest, SHA256};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+use tokio_rustls::{
+    rustls::{self, Certificate, PrivateKey, ServerConfig},
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+/// where the certificate lives inside the config dir
+pub const TLS_DIR: &str = "tls";
+const CERT_FILE: &str = "cert.der";
+const KEY_FILE: &str = "key.der";
+/// the name doesn't matter to pinning clients, it only has to be valid
+const SUBJECT_ALT_NAMES: [&str; 1] = ["localhost"];
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// connections done with their handshake that the server hasn't taken yet
+const HANDSHAKE_BACKLOG: usize = 16;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(io::Error),
+    Generate(rcgen::Error),
+    Config(rustls::Error),
+}
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read or write certificate: {}", e),
+            Self::Generate(e) => write!(f, "failed to generate certificate: {}", e),
+            Self::Config(e) => write!(f, "invalid certificate: {}", e),
+        }
+    }
+}
+
+impl Error for TlsError {}
+
+/// Certificate and private key of the daemon, both DER encoded.
+pub struct Identity {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl Identity {
+    /// Load the certificate from `dir`, generating one on first run. It is
+    /// kept across restarts so that pinned fingerprints stay valid.
+    pub fn load_or_generate(dir: &Path) -> Result<Self, TlsError> {
+        let (cert_path, key_path) = (dir.join(CERT_FILE), dir.join(KEY_FILE));
+        match (fs::read(&cert_path), fs::read(&key_path)) {
+            (Ok(cert), Ok(key)) => return Ok(Self { cert, key }),
+            (Err(e), _) | (_, Err(e)) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(TlsError::Io(e))
+            }
+            _ => (),
+        }
+        log::info!("generating self-signed certificate in {}", dir.display());
+        let cert = rcgen::generate_simple_self_signed(SUBJECT_ALT_NAMES.map(String::from))
+            .map_err(TlsError::Generate)?;
+        let identity = Self {
+            cert: cert.serialize_der().map_err(TlsError::Generate)?,
+            key: cert.serialize_private_key_der(),
+        };
+        fs::create_dir_all(dir).map_err(TlsError::Io)?;
+        write_private(&key_path, &identity.key).map_err(TlsError::Io)?;
+        fs::write(&cert_path, &identity.cert).map_err(TlsError::Io)?;
+        Ok(identity)
+    }
+
+    /// Sha-256 fingerprint of the certificate, what clients pin.
+    pub fn fingerprint(&self) -> String {
+        proto::format_fingerprint(digest(&SHA256, &self.cert).as_ref())
+    }
+
+    pub fn acceptor(&self) -> Result<TlsAcceptor, TlsError> {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(self.cert.clone())],
+                PrivateKey(self.key.clone()),
+            )
+            .map_err(TlsError::Config)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// The key must not be readable by other users.
+fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::File::options();
+    options.create(true).truncate(true).write(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)
+}
+
+/// Accept connections on `listener` for hyper. Handshakes run in their own
+/// tasks, so a stalled client doesn't hold up everyone else. The listener is
+/// closed once hyper drops the returned acceptor.
+pub(crate) fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> impl Accept<Conn = TlsStream<TcpStream>, Error = io::Error> {
+    let (tx, mut rx) = mpsc::channel(HANDSHAKE_BACKLOG);
+    tokio::spawn(async move {
+        loop {
+            let (stream, remote_addr) = tokio::select! {
+                _ = tx.closed() => break,
+                res = listener.accept() => match res {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        // e.g. out of file descriptors, give it a moment
+                        log::error!("failed to accept connection: {}", e);
+                        time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                },
+            };
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(stream).await;
+                    }
+                    Ok(Err(e)) => log::debug!("tls handshake with {} failed: {}", remote_addr, e),
+                    Err(_) => log::debug!("tls handshake with {} timed out", remote_addr),
+                }
+            });
+        }
+    });
+    accept::poll_fn(move |cx| rx.poll_recv(cx).map(|stream| stream.map(Ok)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Identity;
+
+    #[test]
+    fn certificate_is_kept() {
+        let dir = std::env::temp_dir().join(format!("htu-net-tls-{}", std::process::id()));
+        let first = Identity::load_or_generate(&dir).unwrap();
+        let second = Identity::load_or_generate(&dir).unwrap();
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        // 32 bytes as colon separated hex
+        assert_eq!(first.fingerprint().len(), 32 * 3 - 1);
+        first.acceptor().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,618 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+};
+
+use api::{auth::Suffix, dns::Resolver};
+use chrono::Weekday;
+pub use proto::ConfigIssue;
+use serde_json::Value;
+
+use crate::config::parse_time;
+
+const ID_LEN: RangeInclusive<usize> = 8..=12;
+const RETRY_INTERVAL: RangeInclusive<u64> = 1..=3600;
+const KEEPALIVE_INTERVAL: RangeInclusive<u64> = 5..=3600;
+const MIN_TOKEN_LEN: usize = 16;
+
+fn path_to_string(path: &[String]) -> String {
+    let mut s = "$".to_string();
+    for key in path {
+        if key.parse::<usize>().is_ok() {
+            s.push_str(&format!("[{}]", key));
+        } else {
+            s.push('.');
+            s.push_str(key);
+        }
+    }
+    s
+}
+
+/// Collects issues while walking a json value.
+#[derive(Default)]
+pub(crate) struct Validator {
+    path: Vec<String>,
+    issues: Vec<(Vec<String>, String)>,
+}
+
+impl Validator {
+    fn push(&mut self, msg: impl Into<String>) {
+        self.issues.push((self.path.clone(), msg.into()));
+    }
+
+    fn field<F: FnOnce(&mut Self, &Value)>(
+        &mut self,
+        obj: &Value,
+        key: &str,
+        required: bool,
+        f: F,
+    ) {
+        self.path.push(key.to_string());
+        match obj.get(key) {
+            None | Some(Value::Null) if required => self.push("missing field"),
+            None | Some(Value::Null) => (),
+            Some(v) => f(self, v),
+        }
+        self.path.pop();
+    }
+
+    fn items<F: FnMut(&mut Self, &Value)>(&mut self, v: &Value, mut f: F) {
+        let Some(items) = v.as_array() else {
+            self.push("expected an array");
+            return;
+        };
+        for (i, item) in items.iter().enumerate() {
+            self.path.push(i.to_string());
+            f(self, item);
+            self.path.pop();
+        }
+    }
+
+    fn string<'v>(&mut self, v: &'v Value) -> Option<&'v str> {
+        let s = v.as_str();
+        if s.is_none() {
+            self.push("expected a string");
+        }
+        s
+    }
+
+    fn socket_addr(&mut self, v: &Value) -> Option<SocketAddr> {
+        let addr = self.string(v)?;
+        let parsed = addr.parse().ok();
+        if parsed.is_none() {
+            self.push(format!(
+                "invalid address `{}`, expected ip:port like 127.0.0.1:11451",
+                addr
+            ));
+        }
+        parsed
+    }
+
+    fn url(&mut self, v: &Value) {
+        let Some(s) = self.string(v) else {
+            return;
+        };
+        match url::Url::parse(s) {
+            Ok(u) if u.scheme() == "http" || u.scheme() == "https" => (),
+            Ok(u) => self.push(format!("unsupported url scheme `{}`", u.scheme())),
+            Err(e) => self.push(format!("invalid url: {}", e)),
+        }
+    }
+
+    pub(crate) fn user(&mut self, user: &Value) {
+        if !user.is_object() {
+            self.push("expected an object");
+            return;
+        }
+        self.field(user, "id", true, |this, v| {
+            let Some(id) = this.string(v) else {
+                return;
+            };
+            if !id.chars().all(|c| c.is_ascii_digit()) {
+                this.push("student id must only contain digits");
+            } else if !ID_LEN.contains(&id.len()) {
+                this.push(format!(
+                    "student id must be {} to {} digits long, got {}",
+                    ID_LEN.start(),
+                    ID_LEN.end(),
+                    id.len()
+                ));
+            }
+        });
+        self.field(user, "password", true, |this, v| {
+            if let Some(pwd) = this.string(v) {
+                if pwd.is_empty() {
+                    this.push("password must not be empty");
+                }
+            }
+        });
+        self.field(user, "suffix", true, |this, v| {
+            if let Some(suffix) = this.string(v) {
+                if Suffix::parse(suffix).is_none() {
+                    this.push(format!(
+                        "unknown operator suffix `{}`, expected one of {}",
+                        suffix,
+                        Suffix::expected()
+                    ));
+                }
+            }
+        });
+    }
+
+    pub(crate) fn config(&mut self, conf: &Value) {
+        if !conf.is_object() {
+            self.push("expected an object");
+            return;
+        }
+        self.field(conf, "user", false, |this, v| this.user(v));
+        self.field(conf, "last_login_url", false, |this, v| this.url(v));
+        self.field(conf, "logout_url_base", false, |this, v| this.url(v));
+        self.field(conf, "session", false, |this, v| {
+            if !v.is_object() {
+                this.push("expected an object, written by the daemon at login");
+            }
+        });
+        self.field(conf, "login_hold", false, |this, v| {
+            if !v.is_object() {
+                this.push("expected an object, written by the daemon when a login is refused");
+            }
+        });
+        self.field(conf, "retry_interval", false, |this, v| match v.as_u64() {
+            Some(secs) if RETRY_INTERVAL.contains(&secs) => (),
+            _ => this.push(format!(
+                "interval must be a whole number of seconds between {} and {}",
+                RETRY_INTERVAL.start(),
+                RETRY_INTERVAL.end()
+            )),
+        });
+        self.field(conf, "keepalive_interval", false, |this, v| {
+            match v.as_u64() {
+                Some(secs) if KEEPALIVE_INTERVAL.contains(&secs) => (),
+                _ => this.push(format!(
+                    "interval must be a whole number of seconds between {} and {}",
+                    KEEPALIVE_INTERVAL.start(),
+                    KEEPALIVE_INTERVAL.end()
+                )),
+            }
+        });
+        for key in ["logout_on_shutdown", "kick_oldest_on_limit"] {
+            self.field(conf, key, false, |this, v| {
+                if !v.is_boolean() {
+                    this.push("expected true or false");
+                }
+            });
+        }
+        for key in ["low_balance", "low_quota_mb"] {
+            self.field(conf, key, false, |this, v| {
+                if v.as_u64().is_none() {
+                    this.push("expected a whole number, 0 turns the warning off");
+                }
+            });
+        }
+        self.field(conf, "tls", false, |this, v| match v.as_bool() {
+            Some(true) if !cfg!(feature = "tls") => {
+                this.push("this build doesn't support tls, rebuild with the `tls` feature")
+            }
+            Some(_) => (),
+            None => this.push("expected true or false"),
+        });
+        self.field(conf, "gateway", false, |this, v| this.gateway(v));
+        self.field(conf, "schedule", false, |this, v| this.schedule(v));
+        self.field(conf, "dns", false, |this, v| this.dns(v));
+        let has_token = conf.get("api_token").is_some_and(|t| !t.is_null());
+        self.field(conf, "listen", false, |this, v| {
+            if let Some(addr) = this.socket_addr(v) {
+                if !addr.ip().is_loopback() && !has_token {
+                    this.push("listening beyond this machine requires `api_token` to be set")
+                }
+            }
+        });
+        self.field(conf, "api_token", false, |this, v| {
+            if let Some(token) = this.string(v) {
+                if token.chars().count() < MIN_TOKEN_LEN {
+                    this.push(format!(
+                        "token must be at least {} characters long",
+                        MIN_TOKEN_LEN
+                    ));
+                }
+            }
+        });
+    }
+
+    fn gateway(&mut self, gateway: &Value) {
+        if !gateway.is_object() {
+            self.push("expected an object");
+            return;
+        }
+        self.field(gateway, "wan_interface", true, |this, v| {
+            if this.string(v).is_some_and(str::is_empty) {
+                this.push("interface name must not be empty");
+            }
+        });
+        self.field(gateway, "status_listen", false, |this, v| {
+            if this.socket_addr(v).is_some_and(|addr| addr.ip().is_unspecified()) {
+                this.push(
+                    "listening on every interface exposes the status page on the WAN, use the LAN address of this machine",
+                );
+            }
+        });
+    }
+
+    fn dns(&mut self, dns: &Value) {
+        if !dns.is_object() {
+            self.push("expected an object");
+            return;
+        }
+        self.field(dns, "servers", false, |this, v| {
+            this.items(v, |this, server| {
+                if let Some(server) = this.string(server) {
+                    if Resolver::parse_server(server).is_none() {
+                        this.push(format!(
+                            "invalid server `{}`, expected an ip address like 223.5.5.5",
+                            server
+                        ));
+                    }
+                }
+            })
+        });
+        self.field(dns, "hosts", false, |this, v| {
+            let Some(hosts) = v.as_object() else {
+                this.push("expected an object of host names and their addresses");
+                return;
+            };
+            for host in hosts.keys() {
+                this.field(v, host, true, |this, addrs| {
+                    this.items(addrs, |this, addr| {
+                        if let Some(addr) = this.string(addr) {
+                            if addr.parse::<IpAddr>().is_err() {
+                                this.push(format!("invalid ip address `{}`", addr));
+                            }
+                        }
+                    })
+                });
+            }
+        });
+    }
+
+    fn schedule(&mut self, schedule: &Value) {
+        if !schedule.is_object() {
+            self.push("expected an object");
+            return;
+        }
+        self.field(schedule, "windows", true, |this, v| {
+            this.items(v, |this, window| this.window(window))
+        });
+        self.field(schedule, "logout_at_end", false, |this, v| {
+            if !v.is_boolean() {
+                this.push("expected true or false");
+            }
+        });
+    }
+
+    fn window(&mut self, window: &Value) {
+        if !window.is_object() {
+            self.push("expected an object");
+            return;
+        }
+        self.field(window, "days", false, |this, v| {
+            this.items(v, |this, day| {
+                if let Some(day) = this.string(day) {
+                    if day.parse::<Weekday>().is_err() {
+                        this.push(format!("unknown day `{}`, expected mon to sun", day));
+                    }
+                }
+            })
+        });
+        for key in ["start", "end"] {
+            self.field(window, key, true, |this, v| {
+                if let Some(time) = this.string(v) {
+                    if parse_time(time).is_none() {
+                        this.push(format!(
+                            "invalid time `{}`, expected HH:MM like 08:00",
+                            time
+                        ));
+                    }
+                }
+            });
+        }
+    }
+
+    /// Turn the collected issues into [`ConfigIssue`]s, resolving line and
+    /// column against `src` when the source text is available.
+    pub(crate) fn finish(self, src: Option<&str>) -> Vec<ConfigIssue> {
+        self.issues
+            .into_iter()
+            .map(|(path, message)| {
+                let pos = src.and_then(|src| locate(src, &path));
+                ConfigIssue {
+                    path: path_to_string(&path),
+                    message,
+                    line: pos.map(|p| p.0),
+                    column: pos.map(|p| p.1),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parse and validate a json document with `check`, returning the parsed
+/// value or every issue found in it.
+pub(crate) fn check_source(
+    src: &[u8],
+    check: impl FnOnce(&mut Validator, &Value),
+) -> Result<Value, Vec<ConfigIssue>> {
+    match inspect_source(src, check)? {
+        (value, issues) if issues.is_empty() => Ok(value),
+        (_, issues) => Err(issues),
+    }
+}
+
+/// [`check_source`] that only fails on syntax errors, returning the parsed
+/// value along with the issues found in it.
+pub(crate) fn inspect_source(
+    src: &[u8],
+    check: impl FnOnce(&mut Validator, &Value),
+) -> Result<(Value, Vec<ConfigIssue>), Vec<ConfigIssue>> {
+    let value: Value = serde_json::from_slice(src).map_err(|e| vec![syntax_issue(&e)])?;
+    let mut validator = Validator::default();
+    check(&mut validator, &value);
+    let issues = validator.finish(Some(&String::from_utf8_lossy(src)));
+    Ok((value, issues))
+}
+
+pub(crate) fn syntax_issue(e: &serde_json::Error) -> ConfigIssue {
+    let msg = e.to_string();
+    // serde_json appends " at line x column y", which we report separately
+    let message = match msg.rfind(" at line ") {
+        Some(i) if e.line() > 0 => msg[..i].to_string(),
+        _ => msg,
+    };
+    ConfigIssue {
+        path: "$".into(),
+        message,
+        line: (e.line() > 0).then_some(e.line()),
+        column: (e.line() > 0).then_some(e.column()),
+    }
+}
+
+/// Find the 1-based line and column where the value at `path` starts.
+fn locate(src: &str, path: &[String]) -> Option<(usize, usize)> {
+    let bytes = src.as_bytes();
+    let mut pos = skip_ws(bytes, 0);
+    for key in path {
+        pos = match key.parse() {
+            Ok(index) => find_index(bytes, pos, index)?,
+            Err(_) => find_key(bytes, pos, key)?,
+        };
+    }
+    let before = &src[..pos];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+        .chars()
+        .count()
+        + 1;
+    Some((line, column))
+}
+
+fn skip_ws(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Returns the position right after the string starting at `pos`.
+fn skip_string(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    pos += 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+/// Returns the position right after the value starting at `pos`.
+fn skip_value(bytes: &[u8], pos: usize) -> Option<usize> {
+    match bytes.get(pos)? {
+        b'"' => skip_string(bytes, pos),
+        b'{' | b'[' => {
+            let mut depth = 0;
+            let mut pos = pos;
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'"' => {
+                        pos = skip_string(bytes, pos)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(pos + 1);
+                        }
+                    }
+                    _ => (),
+                }
+                pos += 1;
+            }
+            None
+        }
+        _ => {
+            let mut pos = pos;
+            while pos < bytes.len() && !matches!(bytes[pos], b',' | b'}' | b']') {
+                pos += 1;
+            }
+            Some(pos)
+        }
+    }
+}
+
+fn find_key(bytes: &[u8], pos: usize, key: &str) -> Option<usize> {
+    if bytes.get(pos)? != &b'{' {
+        return None;
+    }
+    let mut pos = skip_ws(bytes, pos + 1);
+    while bytes.get(pos)? == &b'"' {
+        let end = skip_string(bytes, pos)?;
+        let name = &bytes[pos + 1..end - 1];
+        pos = skip_ws(bytes, end);
+        if bytes.get(pos)? != &b':' {
+            return None;
+        }
+        pos = skip_ws(bytes, pos + 1);
+        if name == key.as_bytes() {
+            return Some(pos);
+        }
+        pos = skip_ws(bytes, skip_value(bytes, pos)?);
+        if bytes.get(pos)? == &b',' {
+            pos = skip_ws(bytes, pos + 1);
+        }
+    }
+    None
+}
+
+fn find_index(bytes: &[u8], pos: usize, index: usize) -> Option<usize> {
+    if bytes.get(pos)? != &b'[' {
+        return None;
+    }
+    let mut pos = skip_ws(bytes, pos + 1);
+    for _ in 0..index {
+        pos = skip_ws(bytes, skip_value(bytes, pos)?);
+        if bytes.get(pos)? != &b',' {
+            return None;
+        }
+        pos = skip_ws(bytes, pos + 1);
+    }
+    (bytes.get(pos)? != &b']').then_some(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_config(src: &str) -> Vec<ConfigIssue> {
+        check_source(src.as_bytes(), |v, value| v.config(value)).unwrap_err()
+    }
+
+    #[test]
+    fn reports_all_user_issues_with_position() {
+        let src = r#"{
+  "user": {
+    "id": "21a",
+    "password": "",
+    "suffix": "@ydd"
+  },
+  "retry_interval": 0
+}"#;
+        let issues = check_config(src);
+        let paths = issues.iter().map(|i| i.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "$.user.id",
+                "$.user.password",
+                "$.user.suffix",
+                "$.retry_interval"
+            ]
+        );
+        assert_eq!((issues[0].line, issues[0].column), (Some(3), Some(11)));
+        assert_eq!((issues[2].line, issues[2].column), (Some(5), Some(15)));
+        assert!(issues[2].message.contains("@ydd"));
+        assert_eq!((issues[3].line, issues[3].column), (Some(7), Some(21)));
+    }
+
+    #[test]
+    fn reports_missing_fields_and_bad_urls() {
+        let src = r#"{"user": {"id": "2108114040"}, "logout_url_base": "10.101.2.205"}"#;
+        let issues = check_config(src);
+        let paths = issues.iter().map(|i| i.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["$.user.password", "$.user.suffix", "$.logout_url_base"]
+        );
+        assert_eq!(issues[2].column, Some(51));
+    }
+
+    #[test]
+    fn lan_listen_requires_token() {
+        let issues = check_config(r#"{"listen": "0.0.0.0:11451"}"#);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "$.listen");
+        let issues = check_config(r#"{"listen": "0.0.0.0:0", "api_token": "short"}"#);
+        assert_eq!(issues[0].path, "$.api_token");
+        let src = r#"{"listen": "0.0.0.0:0", "api_token": "0123456789abcdef"}"#;
+        assert!(check_source(src.as_bytes(), |v, value| v.config(value)).is_ok());
+    }
+
+    #[test]
+    fn gateway() {
+        let issues = check_config(r#"{"gateway": {"status_listen": "lan:80"}}"#);
+        let paths: Vec<_> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["$.gateway.wan_interface", "$.gateway.status_listen"]
+        );
+        let src = r#"{"gateway": {"wan_interface": "eth0", "status_listen": "0.0.0.0:80"}}"#;
+        assert_eq!(check_config(src)[0].path, "$.gateway.status_listen");
+        let src = r#"{"gateway": {"wan_interface": "eth0", "status_listen": "192.168.10.1:80"}}"#;
+        assert!(check_source(src.as_bytes(), |v, value| v.config(value)).is_ok());
+    }
+
+    #[test]
+    fn schedule() {
+        let src = r#"{"schedule": {"windows": [
+  {"start": "08:00", "end": "23:00"},
+  {"days": ["sat", "holiday"], "start": "8am"}
+]}}"#;
+        let issues = check_config(src);
+        let paths: Vec<_> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "$.schedule.windows[1].days[1]",
+                "$.schedule.windows[1].start",
+                "$.schedule.windows[1].end"
+            ]
+        );
+        assert_eq!((issues[0].line, issues[0].column), (Some(3), Some(20)));
+        assert!(issues[1].message.contains("8am"));
+        let src = r#"{"schedule": {"windows": [{"days": ["Fri"], "start": "22:00", "end": "02:00"}], "logout_at_end": true}}"#;
+        assert!(check_source(src.as_bytes(), |v, value| v.config(value)).is_ok());
+    }
+
+    #[test]
+    fn dns() {
+        let src = r#"{"dns": {"servers": ["223.5.5.5", "dns.alidns.com"], "hosts": {"portal.htu.edu.cn": ["10.101.2.205", "portal"]}}}"#;
+        let issues = check_config(src);
+        let paths: Vec<_> = issues.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["$.dns.servers[1]", "$.dns.hosts.portal.htu.edu.cn[1]"]
+        );
+        let src = r#"{"dns": {"servers": ["[2400:3200::1]:53"], "hosts": {"portal.htu.edu.cn": ["10.101.2.205"]}}}"#;
+        assert!(check_source(src.as_bytes(), |v, value| v.config(value)).is_ok());
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let issues = check_config("{\n  \"user\": }");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "$");
+        assert_eq!(issues[0].line, Some(2));
+        assert!(!issues[0].message.contains("at line"));
+    }
+
+    #[test]
+    fn accepts_valid_config() {
+        let src = r#"{
+  "user": {"id": "2108114040", "password": "pwd", "suffix": "@yd"},
+  "logout_url_base": "http://10.101.2.205",
+  "retry_interval": 5,
+  "logout_on_shutdown": true
+}"#;
+        assert!(check_source(src.as_bytes(), |v, value| v.config(value)).is_ok());
+    }
+}
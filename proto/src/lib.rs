@@ -0,0 +1,728 @@
+//! Messages exchanged between the daemon and its clients over http.
+
+use std::fmt::Display;
+
+use api::{account::AccountError, auth::AuthError, logout::LogoutError};
+pub use api::{
+    account::{AccountInfo, OnlineDevice},
+    auth::{Session, Suffix, UserInfo},
+    portal::PortalContext,
+};
+use serde::{Deserialize, Serialize};
+
+/// Address the daemon listens on unless configured otherwise.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:11451";
+
+/// Directory below the user config dir holding the config, logs and
+/// runtime files.
+pub const APP_DIR: &str = "htu-net";
+
+/// Name of the [`Discovery`] file in [`APP_DIR`].
+pub const DISCOVERY_FILE: &str = "daemon.json";
+
+/// Published by the daemon while it serves the api, so that clients find it
+/// even when it listens on a configured or random port.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Discovery {
+    /// local address to connect to, e.g. `127.0.0.1:11451`, or an
+    /// `https://` url when the api is served over tls
+    pub addr: String,
+    pub pid: u32,
+    /// fingerprint of the daemon's certificate when served over tls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// Format a certificate digest the way the daemon prints fingerprints,
+/// e.g. `AB:CD:…`.
+pub fn format_fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Request paths served by the daemon.
+pub mod path {
+    pub const PING: &str = "/api/v1/ping";
+    /// `GET` returns the account, `POST` replaces it
+    pub const USER: &str = "/api/v1/user";
+    pub const STATUS: &str = "/api/v1/status";
+    pub const HISTORY: &str = "/api/v1/history";
+    /// newline delimited json stream of [`Event`](crate::Event)s
+    pub const EVENTS: &str = "/api/v1/events";
+    pub const LOGIN: &str = "/api/v1/login";
+    pub const LOGOUT: &str = "/api/v1/logout";
+    /// balance, traffic and online devices, `?refresh=true` skips the cache
+    pub const ACCOUNT: &str = "/api/v1/account";
+    /// `GET` lists the sessions of the account, `POST` forces one offline
+    pub const SESSIONS: &str = "/api/v1/sessions";
+    pub const SHUTDOWN: &str = "/api/v1/shutdown";
+    pub const OPENAPI: &str = "/api/v1/openapi.json";
+    /// Prometheus metrics in the text exposition format
+    pub const METRICS: &str = "/metrics";
+
+    /// Shutdown path of daemons from before the versioned api.
+    pub const LEGACY_EXIT: &str = "/exit";
+}
+
+/// Data of `GET /api/v1/user`, `null` when no account is set.
+pub type UserResponse = Option<UserInfo>;
+
+/// Every response body of the api is wrapped in an envelope.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub ok: bool,
+    pub data: Option<T>,
+    pub error: Option<ApiError>,
+}
+
+impl<T> Envelope<T> {
+    pub fn data(data: T) -> Self {
+        Self {
+            ok: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub fn error(error: ApiError) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(error),
+        }
+    }
+
+    pub fn into_result(self) -> Result<Option<T>, ApiError> {
+        match self.error {
+            Some(e) => Err(e),
+            None if !self.ok => Err(ApiError::new(ErrorCode::Unknown, "request failed")),
+            None => Ok(self.data),
+        }
+    }
+}
+
+impl Envelope<()> {
+    /// Successful response without data.
+    pub fn empty() -> Self {
+        Self {
+            ok: true,
+            data: None,
+            error: None,
+        }
+    }
+}
+
+/// Machine readable reason of a failed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    /// missing or wrong api token
+    Unauthorized,
+    /// cross site request from a browser
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    UserNotSet,
+    /// the portal rejected the credentials
+    AuthFailed,
+    AlreadyOnline,
+    /// another login is talking to the portal right now
+    LoginInProgress,
+    PortalUnreachable,
+    PortalInvalidResponse,
+    /// the portal refused to log out, usually because there is no session
+    LogoutRejected,
+    /// there is no session to log out of
+    NotLoggedIn,
+    /// the portal didn't tell the account details, usually because there is
+    /// no session
+    AccountUnavailable,
+    Internal,
+    /// a code this client doesn't know about yet
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::InvalidRequest => 400,
+            Self::Unauthorized | Self::AuthFailed => 401,
+            Self::Forbidden => 403,
+            Self::NotFound => 404,
+            Self::MethodNotAllowed => 405,
+            Self::UserNotSet
+            | Self::AlreadyOnline
+            | Self::LoginInProgress
+            | Self::LogoutRejected
+            | Self::NotLoggedIn
+            | Self::AccountUnavailable => 409,
+            Self::Internal | Self::Unknown => 500,
+            Self::PortalInvalidResponse => 502,
+            Self::PortalUnreachable => 503,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// validation problems of the request body, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            issues: vec![],
+        }
+    }
+
+    pub fn invalid(issues: Vec<ConfigIssue>) -> Self {
+        Self {
+            code: ErrorCode::InvalidRequest,
+            message: issues
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            issues,
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<&AuthError> for ApiError {
+    fn from(e: &AuthError) -> Self {
+        let code = match e {
+            AuthError::ReqError(_) => ErrorCode::PortalUnreachable,
+            AuthError::InvalidResponse(_) => ErrorCode::PortalInvalidResponse,
+            AuthError::AuthFailed { .. } => ErrorCode::AuthFailed,
+            AuthError::Authed => ErrorCode::AlreadyOnline,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<&LogoutError> for ApiError {
+    fn from(e: &LogoutError) -> Self {
+        let code = match e {
+            LogoutError::Request(_) => ErrorCode::PortalUnreachable,
+            LogoutError::JSON(_) => ErrorCode::LogoutRejected,
+            LogoutError::NotLoggedIn => ErrorCode::NotLoggedIn,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<&AccountError> for ApiError {
+    fn from(e: &AccountError) -> Self {
+        let code = match e {
+            AccountError::Request(_) => ErrorCode::PortalUnreachable,
+            AccountError::Rejected(_) => ErrorCode::AccountUnavailable,
+            AccountError::InvalidResponse(_) => ErrorCode::PortalInvalidResponse,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+/// A single problem found in a config or request body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigIssue {
+    /// JSON path of the offending value, e.g. `$.user.suffix`
+    pub path: String,
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, " (line {}, column {})", line, column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Body of `GET /status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub version: String,
+    /// account id of the configured user
+    pub account: Option<String>,
+    /// unix timestamp of the last successful login
+    pub last_login: Option<u64>,
+    /// message of the last failed login, cleared on success
+    pub last_error: Option<String>,
+    /// recent subsystem crashes, oldest first
+    #[serde(default)]
+    pub crashes: Vec<Crash>,
+    /// sha-256 fingerprint of the certificate when the api is served over tls
+    #[serde(default)]
+    pub tls_fingerprint: Option<String>,
+    /// upstream state when the daemon logs in on behalf of a LAN
+    #[serde(default)]
+    pub gateway: Option<GatewayStatus>,
+    #[serde(default)]
+    pub keepalive: Option<KeepaliveStatus>,
+    /// login windows, none when auto-login is allowed at any time
+    #[serde(default)]
+    pub schedule: Option<ScheduleStatus>,
+    /// auto-login held back after the portal refused for good
+    #[serde(default)]
+    pub paused: Option<LoginPause>,
+    /// what the last login left behind, none once logged out
+    #[serde(default)]
+    pub session: Option<Session>,
+    /// what the latest portal redirect said about this machine
+    #[serde(default)]
+    pub portal: Option<PortalContext>,
+    /// which address families reached the internet at the last probe
+    #[serde(default)]
+    pub connectivity: Option<Connectivity>,
+    /// problems of the config the daemon started on anyway
+    #[serde(default)]
+    pub config_issues: Vec<ConfigIssue>,
+}
+
+/// Reachability of the internet per address family, probed separately since
+/// a dual-stack campus may let one through while the other waits for the
+/// portal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connectivity {
+    pub ipv4: bool,
+    pub ipv6: bool,
+    /// what the probe host resolved to when that can't be right, i.e. the
+    /// portal hijacked the DNS
+    #[serde(default)]
+    pub hijacked: Option<String>,
+    /// unix timestamp of the probe
+    pub checked_at: u64,
+}
+
+/// Why and until when auto-login is held back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginPause {
+    /// what the portal refused for, e.g. `账号欠费或套餐已到期`
+    pub reason: String,
+    /// unix timestamp auto-login resumes at, none when it waits for the
+    /// account to change
+    pub until: Option<u64>,
+}
+
+/// Where the daemon is in its configured login windows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleStatus {
+    /// whether auto-login is allowed right now
+    pub allowed: bool,
+    /// unix timestamp when that changes next
+    pub next_change: Option<u64>,
+}
+
+/// Probes keeping the portal session from timing out while idle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeepaliveStatus {
+    /// seconds between probes
+    pub interval: u64,
+    /// whether the interval was learned rather than configured
+    pub learned: bool,
+    /// unix timestamp of the last probe
+    pub last_probe: Option<u64>,
+    /// sessions that dropped despite the probes
+    pub drops: u32,
+}
+
+/// State of the WAN interface a daemon in gateway mode logs in through.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayStatus {
+    /// name of the WAN interface, e.g. `eth0`
+    pub interface: String,
+    /// its address, none while it has none
+    pub address: Option<String>,
+    /// whether the last keep-alive probe got through
+    pub online: bool,
+    /// unix timestamp of the last probe
+    pub last_probe: Option<u64>,
+    /// why the WAN can't be used, e.g. the interface is gone
+    pub error: Option<String>,
+}
+
+/// Data of `GET /api/v1/account`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    pub info: AccountInfo,
+    /// unix timestamp of when the portal was asked
+    pub fetched_at: u64,
+    /// what is running low, e.g. the balance
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+/// Body of `POST /api/v1/sessions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KickRequest {
+    /// [`OnlineDevice::session`] of the session to force offline
+    pub session: String,
+}
+
+/// A daemon subsystem that panicked or stopped and was restarted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crash {
+    /// `login`, `server`, `config watcher`, `gateway status`, `schedule` or
+    /// `account`
+    pub subsystem: String,
+    /// unix timestamp
+    pub at: u64,
+    pub reason: String,
+}
+
+/// One login attempt, `GET /api/v1/history` returns the latest first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginRecord {
+    /// unix timestamp
+    pub at: u64,
+    pub ok: bool,
+    pub msg: Option<String>,
+}
+
+/// Things that happened in the daemon, in the order they happened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    LoginSucceeded {
+        at: u64,
+    },
+    LoginFailed {
+        msg: String,
+    },
+    LoggedOut,
+    ConfigReloaded {
+        changes: Vec<String>,
+    },
+    /// the balance or traffic of the account started running low
+    AccountLow {
+        warnings: Vec<String>,
+    },
+    /// a session of the account was forced offline, `automatic` when the
+    /// daemon did it to get past the device limit
+    SessionKicked {
+        ip: String,
+        automatic: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::json;
+
+    use super::*;
+
+    fn round_trip<T>(value: T, wire: serde_json::Value)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(serde_json::to_value(&value).unwrap(), wire);
+        assert_eq!(serde_json::from_value::<T>(wire).unwrap(), value);
+    }
+
+    #[test]
+    fn user() {
+        round_trip::<UserResponse>(
+            Some(UserInfo::new("2108114040", "pwd", Suffix::ChinaMobile)),
+            json!({ "id": "2108114040", "password": "pwd", "suffix": "@yd" }),
+        );
+        round_trip::<UserResponse>(None, json!(null));
+        for suffix in Suffix::ALL {
+            round_trip(suffix, json!(suffix.to_str()));
+        }
+    }
+
+    #[test]
+    fn envelope() {
+        round_trip(
+            Envelope::empty(),
+            json!({ "ok": true, "data": null, "error": null }),
+        );
+        round_trip(
+            Envelope::data(Some(UserInfo::new("2108114040", "pwd", Suffix::Local))),
+            json!({
+                "ok": true,
+                "data": { "id": "2108114040", "password": "pwd", "suffix": "@hsd" },
+                "error": null,
+            }),
+        );
+        let issue = ConfigIssue {
+            path: "$.suffix".into(),
+            message: "unknown operator suffix".into(),
+            line: Some(1),
+            column: Some(12),
+        };
+        round_trip(
+            Envelope::<()>::error(ApiError::invalid(vec![issue])),
+            json!({
+                "ok": false,
+                "data": null,
+                "error": {
+                    "code": "invalid_request",
+                    "message": "$.suffix (line 1, column 12): unknown operator suffix",
+                    "issues": [{
+                        "path": "$.suffix",
+                        "message": "unknown operator suffix",
+                        "line": 1,
+                        "column": 12,
+                    }],
+                },
+            }),
+        );
+    }
+
+    #[test]
+    fn error_code() {
+        let e = ApiError::from(&AuthError::AuthFailed {
+            msg: "密码错误".into(),
+        });
+        assert_eq!(e.code, ErrorCode::AuthFailed);
+        assert_eq!(e.code.status(), 401);
+        assert_eq!(ApiError::from(&AuthError::Authed).code.status(), 409);
+        let e = ApiError::from(&LogoutError::NotLoggedIn);
+        assert_eq!(e.code, ErrorCode::NotLoggedIn);
+        round_trip(e.code, json!("not_logged_in"));
+        round_trip(ErrorCode::PortalUnreachable, json!("portal_unreachable"));
+        assert_eq!(
+            serde_json::from_value::<ErrorCode>(json!("from_the_future")).unwrap(),
+            ErrorCode::Unknown
+        );
+    }
+
+    #[test]
+    fn status() {
+        round_trip(
+            Status {
+                version: "0.1.2".into(),
+                account: Some("2108114040".into()),
+                last_login: Some(1700000000),
+                last_error: None,
+                crashes: vec![Crash {
+                    subsystem: "server".into(),
+                    at: 1700000000,
+                    reason: "panicked".into(),
+                }],
+                tls_fingerprint: Some(format_fingerprint(&[0xab, 0x01, 0xff])),
+                gateway: Some(GatewayStatus {
+                    interface: "eth0".into(),
+                    address: Some("10.102.3.4".into()),
+                    online: true,
+                    last_probe: Some(1700000030),
+                    error: None,
+                }),
+                keepalive: Some(KeepaliveStatus {
+                    interval: 40,
+                    learned: true,
+                    last_probe: Some(1700000040),
+                    drops: 1,
+                }),
+                schedule: Some(ScheduleStatus {
+                    allowed: false,
+                    next_change: Some(1700028000),
+                }),
+                paused: Some(LoginPause {
+                    reason: "账号已停用".into(),
+                    until: Some(1700001800),
+                }),
+                session: Some(Session {
+                    portal: "http://10.101.2.205".into(),
+                    url: "http://10.101.2.205/index.html?userip=10.102.3.4".into(),
+                    args: vec![("userip".into(), "10.102.3.4".into())],
+                    logout_url_base: "http://10.101.2.205".into(),
+                    logout_url: "http://10.101.2.205/loginOut".into(),
+                    account: "2108114040@yd".into(),
+                    logged_in_at: 1700000000,
+                    responses: vec![json!({ "code": "0" })],
+                }),
+                portal: Some(PortalContext {
+                    user_ip: Some("10.102.3.4".into()),
+                    ac_name: Some("HTU-AC-01".into()),
+                    extras: [("page".into(), "pc".into())].into(),
+                    ..Default::default()
+                }),
+                connectivity: Some(Connectivity {
+                    ipv4: true,
+                    ipv6: false,
+                    hijacked: None,
+                    checked_at: 1700000040,
+                }),
+                config_issues: vec![ConfigIssue {
+                    path: "$.listen".into(),
+                    message: "listening beyond this machine requires `api_token` to be set".into(),
+                    line: Some(2),
+                    column: Some(13),
+                }],
+            },
+            json!({
+                "version": "0.1.2",
+                "account": "2108114040",
+                "last_login": 1700000000,
+                "last_error": null,
+                "crashes": [{ "subsystem": "server", "at": 1700000000, "reason": "panicked" }],
+                "tls_fingerprint": "AB:01:FF",
+                "gateway": {
+                    "interface": "eth0",
+                    "address": "10.102.3.4",
+                    "online": true,
+                    "last_probe": 1700000030,
+                    "error": null,
+                },
+                "keepalive": { "interval": 40, "learned": true, "last_probe": 1700000040, "drops": 1 },
+                "schedule": { "allowed": false, "next_change": 1700028000 },
+                "paused": { "reason": "账号已停用", "until": 1700001800 },
+                "session": {
+                    "portal": "http://10.101.2.205",
+                    "url": "http://10.101.2.205/index.html?userip=10.102.3.4",
+                    "args": [["userip", "10.102.3.4"]],
+                    "logout_url_base": "http://10.101.2.205",
+                    "logout_url": "http://10.101.2.205/loginOut",
+                    "account": "2108114040@yd",
+                    "logged_in_at": 1700000000,
+                    "responses": [{ "code": "0" }],
+                },
+                "portal": {
+                    "user_ip": "10.102.3.4",
+                    "mac": null,
+                    "ac_name": "HTU-AC-01",
+                    "ac_ip": null,
+                    "vlan": null,
+                    "ssid": null,
+                    "extras": { "page": "pc" },
+                },
+                "connectivity": { "ipv4": true, "ipv6": false, "hijacked": null, "checked_at": 1700000040 },
+                "config_issues": [{
+                    "path": "$.listen",
+                    "message": "listening beyond this machine requires `api_token` to be set",
+                    "line": 2,
+                    "column": 13,
+                }],
+            }),
+        );
+        // daemons from before crashes were recorded
+        let old =
+            json!({ "version": "0.1.2", "account": null, "last_login": null, "last_error": null });
+        assert!(serde_json::from_value::<Status>(old)
+            .unwrap()
+            .crashes
+            .is_empty());
+    }
+
+    #[test]
+    fn discovery() {
+        round_trip(
+            Discovery {
+                addr: "127.0.0.1:11451".into(),
+                pid: 42,
+                fingerprint: None,
+            },
+            json!({ "addr": "127.0.0.1:11451", "pid": 42 }),
+        );
+    }
+
+    #[test]
+    fn history() {
+        round_trip(
+            vec![LoginRecord {
+                at: 1700000000,
+                ok: false,
+                msg: Some("密码错误".into()),
+            }],
+            json!([{ "at": 1700000000, "ok": false, "msg": "密码错误" }]),
+        );
+    }
+
+    #[test]
+    fn event() {
+        round_trip(
+            Event::LoginSucceeded { at: 1700000000 },
+            json!({ "type": "login_succeeded", "at": 1700000000 }),
+        );
+        round_trip(
+            Event::LoginFailed {
+                msg: "密码错误".into(),
+            },
+            json!({ "type": "login_failed", "msg": "密码错误" }),
+        );
+        round_trip(Event::LoggedOut, json!({ "type": "logged_out" }));
+        round_trip(
+            Event::ConfigReloaded {
+                changes: vec!["user".into()],
+            },
+            json!({ "type": "config_reloaded", "changes": ["user"] }),
+        );
+        round_trip(
+            Event::AccountLow {
+                warnings: vec!["余额不足".into()],
+            },
+            json!({ "type": "account_low", "warnings": ["余额不足"] }),
+        );
+        round_trip(
+            Event::SessionKicked {
+                ip: "10.102.3.4".into(),
+                automatic: true,
+            },
+            json!({ "type": "session_kicked", "ip": "10.102.3.4", "automatic": true }),
+        );
+    }
+
+    #[test]
+    fn account() {
+        round_trip(
+            Account {
+                info: AccountInfo {
+                    package: None,
+                    balance: Some(-150),
+                    used_mb: Some(10240),
+                    quota_mb: None,
+                    expires: Some("2024-07-01".into()),
+                    devices: vec![OnlineDevice {
+                        session: Some("c0a8f3e1-0001".into()),
+                        ip: "10.102.3.4".into(),
+                        mac: None,
+                        login_time: None,
+                    }],
+                },
+                fetched_at: 1700000000,
+                warnings: vec![],
+            },
+            json!({
+                "info": {
+                    "package": null,
+                    "balance": -150,
+                    "used_mb": 10240,
+                    "quota_mb": null,
+                    "expires": "2024-07-01",
+                    "devices": [{
+                        "session": "c0a8f3e1-0001",
+                        "ip": "10.102.3.4",
+                        "mac": null,
+                        "login_time": null,
+                    }],
+                },
+                "fetched_at": 1700000000,
+                "warnings": [],
+            }),
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crossterm::event::{KeyCode, KeyEventKind};
+use ratatui::{
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Paragraph},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use proto::Account;
+
+use crate::data::{Action, AppPage, DaemonRequest, Signal};
+
+use super::{
+    util::{centered_box_sized, format_wait},
+    Component, ComponentInfo,
+};
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Loading,
+    Loaded(Box<Account>),
+    Failed,
+}
+
+/// Balance, traffic and online devices of the account.
+#[derive(Default)]
+pub struct AccountPage {
+    mouse_area: Rect,
+    state: State,
+    action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl Component for AccountPage {
+    fn init(&mut self) -> crate::Result<ComponentInfo> {
+        Ok(ComponentInfo::all_enabled())
+    }
+
+    fn register_action_sender(&mut self, sender: UnboundedSender<Action>) -> crate::Result<()> {
+        sender.send(Action::GetAccountInfo(false)).unwrap();
+        self.action_tx = Some(sender);
+        Ok(())
+    }
+
+    fn handle_signal(&mut self, signal: Signal) -> crate::Result<()> {
+        match signal {
+            Signal::AccountInfo(account) => self.state = State::Loaded(account),
+            Signal::DaemonResponse {
+                req: DaemonRequest::AccountInfo,
+                ..
+            } => self.state = State::Failed,
+            _ => return Ok(()),
+        }
+        self.action_tx.as_ref().unwrap().send(Action::Draw).unwrap();
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: crossterm::event::KeyEvent) -> crate::Result<()> {
+        if key.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        let action_tx = self.action_tx.as_ref().unwrap();
+        match key.code {
+            KeyCode::Char('r') | KeyCode::Char('R') => {
+                self.state = State::Loading;
+                action_tx.send(Action::GetAccountInfo(true)).unwrap();
+                action_tx.send(Action::Draw).unwrap();
+            }
+            KeyCode::Esc => action_tx.send(Action::JumpTo(AppPage::Menu)).unwrap(),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, f: &mut Frame, rect: Rect) -> crate::Result<()> {
+        let block = Block::bordered()
+            .border_style(Style::default().blue())
+            .title("账户信息")
+            .title_bottom(Line::from("<R>刷新 <ESC>返回上一级菜单").centered());
+        let area = centered_box_sized(rect, 48, 16).centered;
+        let text = match &self.state {
+            State::Loading => Text::from("正在获取账户信息...".yellow()),
+            State::Failed => Text::from("获取失败，按<R>重试".red()),
+            State::Loaded(account) => account_lines(account),
+        };
+        f.render_widget(Paragraph::new(text).block(block), area);
+        self.mouse_area = rect;
+        Ok(())
+    }
+
+    fn mouse_area(&self) -> Rect {
+        self.mouse_area
+    }
+}
+
+fn yuan(fen: i64) -> String {
+    let sign = if fen < 0 { "-" } else { "" };
+    let fen = fen.unsigned_abs();
+    format!("{}{}.{:02} 元", sign, fen / 100, fen % 100)
+}
+
+fn unknown(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "未知".to_string())
+}
+
+fn account_lines(account: &Account) -> Text<'static> {
+    let info = &account.info;
+    let traffic = match (info.used_mb, info.quota_mb) {
+        (used, Some(quota)) => format!("{} / {} MB", used.unwrap_or_default(), quota),
+        (Some(used), None) => format!("已用 {} MB，不限量", used),
+        (None, None) => "未知".to_string(),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut lines = vec![
+        Line::from(format!("套餐: {}", unknown(info.package.clone()))),
+        Line::from(format!("余额: {}", unknown(info.balance.map(yuan)))),
+        Line::from(format!("流量: {}", traffic)),
+        Line::from(format!("到期: {}", unknown(info.expires.clone()))),
+        Line::from(format!("在线设备: {} 台", info.devices.len())),
+    ];
+    for device in &info.devices {
+        lines.push(
+            Line::from(format!(
+                "  {} {}",
+                device.ip,
+                device.mac.as_deref().unwrap_or_default()
+            ))
+            .style(Style::default().dim()),
+        );
+    }
+    for warning in &account.warnings {
+        lines.push(Line::from(warning.clone().red().bold()));
+    }
+    lines.push(
+        Line::from(format!(
+            "{}前更新",
+            format_wait(now.saturating_sub(account.fetched_at))
+        ))
+        .style(Style::default().dim())
+        .right_aligned(),
+    );
+    Text::from(lines)
+}
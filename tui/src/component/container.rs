@@ -16,6 +16,15 @@ pub struct AppContainer {
     page: Option<Page>,
     page_info: Option<ComponentInfo>,
     act_tx: Option<UnboundedSender<Action>>,
+    /// address of the daemon when it runs on another machine
+    remote: Option<String>,
+}
+
+impl AppContainer {
+    pub fn with_remote(mut self, remote: impl Into<String>) -> Self {
+        self.remote = Some(remote.into());
+        self
+    }
 }
 
 impl Component for AppContainer {
@@ -63,10 +72,14 @@ impl Component for AppContainer {
         f: &mut ratatui::prelude::Frame,
         rect: ratatui::prelude::Rect,
     ) -> crate::Result<()> {
+        let title = match &self.remote {
+            Some(remote) => format!("HTU NET LOGIN @ {}", remote),
+            None => "HTU NET LOGIN".to_string(),
+        };
         let block = Block::new()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
-            .title(Line::from("HTU NET LOGIN").centered())
+            .title(Line::from(title).centered())
             .title_bottom(Line::from("<Ctrl> + <q> 退出程序").centered())
             .border_style(Style::default().red());
         let inner_area = block.inner(rect);
@@ -2,6 +2,7 @@ use std::sync::atomic::AtomicU16;
 
 use crossterm::event::{MouseButton, MouseEventKind};
 use lazy_static::lazy_static;
+use proto::Suffix;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Position, Rect},
     style::Stylize,
@@ -10,7 +11,7 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use crate::{
     component::{Component, ComponentInfo},
-    data::{Action, Signal, Suffix},
+    data::{Action, Signal},
 };
 
 pub struct Checkbox {
@@ -11,7 +11,9 @@ use ratatui::{
 };
 use tokio::sync::mpsc::UnboundedSender;
 
-use crate::data::{Action, AppPage, Signal, UserInfo};
+use proto::UserInfo;
+
+use crate::data::{Action, AppPage, Signal};
 
 use self::{checkbox::Group, input::Input};
 
@@ -20,17 +22,13 @@ use super::{
     Component, ComponentInfo,
 };
 
+#[derive(Default)]
 enum SelectedInput {
+    #[default]
     Id,
     Pwd,
 }
 
-impl Default for SelectedInput {
-    fn default() -> Self {
-        Self::Id
-    }
-}
-
 #[derive(Default)]
 pub struct AccountForm {
     mouse_area: Rect,
@@ -73,11 +71,11 @@ impl Component for AccountForm {
                     self.action_tx
                         .as_ref()
                         .unwrap()
-                        .send(Action::SetAccount(UserInfo {
-                            id: self.id.content().into(),
-                            password: self.password.content().into(),
-                            suffix: self.checkbox_group.selected(),
-                        }))
+                        .send(Action::SetAccount(UserInfo::new(
+                            self.id.content(),
+                            self.password.content(),
+                            self.checkbox_group.selected(),
+                        )))
                         .unwrap();
                     return Ok(());
                 }
@@ -110,20 +108,18 @@ impl Component for AccountForm {
 
     fn handle_signal(&mut self, signal: crate::data::Signal) -> crate::Result<()> {
         match signal {
-            Signal::DaemonPong => {
-                if !self.pong {
-                    self.action_tx
-                        .as_ref()
-                        .unwrap()
-                        .send(Action::GetAccount)
-                        .unwrap();
-                    self.pong = true;
-                }
+            Signal::DaemonPong if !self.pong => {
+                self.action_tx
+                    .as_ref()
+                    .unwrap()
+                    .send(Action::GetAccount)
+                    .unwrap();
+                self.pong = true;
             }
             Signal::UserInfo(user) => {
-                *self.id.content_mut() = user.id;
-                *self.password.content_mut() = user.password;
-                self.checkbox_group.select(user.suffix);
+                *self.id.content_mut() = user.id().to_string();
+                *self.password.content_mut() = user.password().to_string();
+                self.checkbox_group.select(user.suffix());
                 self.action_tx.as_ref().unwrap().send(Action::Draw).unwrap();
             }
             Signal::InputSelected(id) => {
@@ -157,11 +153,11 @@ impl Component for AccountForm {
                 self.action_tx
                     .as_ref()
                     .unwrap()
-                    .send(Action::SetAccount(UserInfo {
-                        id: self.id.content().into(),
-                        password: self.password.content().into(),
-                        suffix: self.checkbox_group.selected(),
-                    }))
+                    .send(Action::SetAccount(UserInfo::new(
+                        self.id.content(),
+                        self.password.content(),
+                        self.checkbox_group.selected(),
+                    )))
                     .unwrap();
             }
             Ok(())
@@ -11,30 +11,34 @@ use crate::data::{Action, AppPage};
 
 use super::{util::centered_box_sized, Component, ComponentInfo};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 enum Selection {
+    #[default]
     SetUser,
+    Account,
+    Sessions,
+    Login,
     Logout,
 }
 
-impl Default for Selection {
-    fn default() -> Self {
-        Self::SetUser
-    }
-}
-
 #[derive(Default)]
 pub struct Menu {
     selecton: usize,
     mouse_area: Rect,
-    menu: [Selection; 2],
-    sel_mouse_area: [Rect; 2],
+    menu: [Selection; 5],
+    sel_mouse_area: [Rect; 5],
     action_tx: Option<UnboundedSender<Action>>,
 }
 
 impl Component for Menu {
     fn init(&mut self) -> crate::Result<super::ComponentInfo> {
-        self.menu = [Selection::SetUser, Selection::Logout];
+        self.menu = [
+            Selection::SetUser,
+            Selection::Account,
+            Selection::Sessions,
+            Selection::Login,
+            Selection::Logout,
+        ];
         Ok(ComponentInfo::all_enabled())
     }
 
@@ -54,6 +58,9 @@ impl Component for Menu {
             .title("菜单")
             .title_bottom(Line::from("键盘上下切换选项").centered());
         let layout = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
             Constraint::Length(1),
             Constraint::Length(1),
             Constraint::Min(0),
@@ -63,16 +70,22 @@ impl Component for Menu {
         f.render_widget(block, areas.centered);
         f.render_widget(
             "鼠标左键/<Enter>确定选项".yellow().to_centered_line(),
-            layout[3],
+            layout[6],
         );
         f.render_widget("设定账号".reset(), layout[0]);
-        f.render_widget("登出校园网".reset(), layout[1]);
+        f.render_widget("账户信息".reset(), layout[1]);
+        f.render_widget("在线设备".reset(), layout[2]);
+        f.render_widget("立即登录".reset(), layout[3]);
+        f.render_widget("登出校园网".reset(), layout[4]);
         match self.menu[self.selecton] {
             Selection::SetUser => f.render_widget("> 设定账号".green().underlined(), layout[0]),
-            Selection::Logout => f.render_widget("> 登出校园网".red().underlined(), layout[1]),
+            Selection::Account => f.render_widget("> 账户信息".green().underlined(), layout[1]),
+            Selection::Sessions => f.render_widget("> 在线设备".green().underlined(), layout[2]),
+            Selection::Login => f.render_widget("> 立即登录".green().underlined(), layout[3]),
+            Selection::Logout => f.render_widget("> 登出校园网".red().underlined(), layout[4]),
         }
 
-        self.sel_mouse_area = [layout[0], layout[1]];
+        self.sel_mouse_area = [layout[0], layout[1], layout[2], layout[3], layout[4]];
         self.mouse_area = areas.centered;
         Ok(())
     }
@@ -126,6 +139,24 @@ impl Menu {
                 .unwrap()
                 .send(Action::JumpTo(AppPage::Form))
                 .unwrap(),
+            Selection::Account => self
+                .action_tx
+                .as_ref()
+                .unwrap()
+                .send(Action::JumpTo(AppPage::Account))
+                .unwrap(),
+            Selection::Sessions => self
+                .action_tx
+                .as_ref()
+                .unwrap()
+                .send(Action::JumpTo(AppPage::Sessions))
+                .unwrap(),
+            Selection::Login => self
+                .action_tx
+                .as_ref()
+                .unwrap()
+                .send(Action::Login)
+                .unwrap(),
             Selection::Logout => self
                 .action_tx
                 .as_ref()
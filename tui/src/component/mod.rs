@@ -1,7 +1,9 @@
+pub mod account;
 pub mod container;
 pub mod form;
 pub mod menu;
 pub mod page;
+pub mod sessions;
 pub mod util;
 
 use crossterm::event::{KeyEvent, MouseEvent};
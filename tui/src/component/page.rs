@@ -3,9 +3,11 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use client::ClientError;
+use proto::{ErrorCode, LoginPause, PortalContext, ScheduleStatus};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Position, Rect},
     style::{Style, Stylize},
@@ -19,7 +21,17 @@ use tokio::{
 
 use crate::data::{Action, AppPage, DaemonRequest, Level, Notification, Signal};
 
-use super::{form::AccountForm, menu::Menu, util::str_to_lines, Component, ComponentInfo};
+use super::{
+    account::AccountPage,
+    form::AccountForm,
+    menu::Menu,
+    sessions::SessionsPage,
+    util::{format_wait, str_to_lines},
+    Component, ComponentInfo,
+};
+
+/// pings between status fetches, the daemon logs every status request
+const STATUS_EVERY: u32 = 5;
 
 #[derive(Default)]
 pub struct Page {
@@ -31,6 +43,12 @@ pub struct Page {
     mouse_area: Rect,
     exit_state: Arc<AtomicBool>,
     connected: bool,
+    /// where the daemon is in its login windows
+    schedule: Option<String>,
+    /// why auto-login is held back, shown until it isn't
+    paused: Option<String>,
+    /// where the portal says this machine is
+    portal: Option<String>,
 }
 
 impl Component for Page {
@@ -47,8 +65,14 @@ impl Component for Page {
         self.action_tx = Some(sender);
         let state = self.exit_state.clone();
         tokio::spawn(async move {
+            let mut until_status = 0;
             while !state.load(Ordering::SeqCst) {
                 tx.send(Action::PingDaemon).unwrap();
+                if until_status == 0 {
+                    tx.send(Action::GetStatus).unwrap();
+                    until_status = STATUS_EVERY;
+                }
+                until_status -= 1;
                 time::sleep(Duration::from_secs(1)).await;
             }
         });
@@ -57,18 +81,39 @@ impl Component for Page {
 
     fn draw(&mut self, f: &mut ratatui::prelude::Frame, rect: Rect) -> crate::Result<()> {
         self.mouse_area = rect;
+        let paused = self.paused.as_ref().filter(|_| self.connected);
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .constraints([
+                Constraint::Length(paused.is_some() as u16),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
             .split(rect);
+        if let Some(paused) = paused {
+            f.render_widget(
+                Line::from(paused.as_str().bold().white().on_red()).centered(),
+                layout[0],
+            );
+        }
+        let layout = &layout[1..];
         f.render_widget(
-            Line::from(vec!["守护进程".light_cyan(), " ".into(), {
-                if self.connected {
-                    "可用".bold().green()
-                } else {
-                    "不可用".bold().red()
+            Line::from({
+                let mut spans = vec!["守护进程".light_cyan(), " ".into(), {
+                    if self.connected {
+                        "可用".bold().green()
+                    } else {
+                        "不可用".bold().red()
+                    }
+                }];
+                if let Some(schedule) = self.schedule.as_ref().filter(|_| self.connected) {
+                    spans.extend(["  登录时段".light_cyan(), " ".into(), schedule.into()]);
+                }
+                if let Some(portal) = self.portal.as_ref().filter(|_| self.connected) {
+                    spans.extend(["  接入".light_cyan(), " ".into(), portal.into()]);
                 }
-            }])
+                spans
+            })
             .centered(),
             layout[1],
         );
@@ -83,6 +128,7 @@ impl Component for Page {
                 let mut v = vec![Line::from({
                     match notification.level {
                         Level::Info => "信息".bold().green(),
+                        Level::Warning => "警告".bold().yellow(),
                         Level::Error => "错误".bold().red(),
                     }
                 })
@@ -107,10 +153,33 @@ impl Component for Page {
     }
 
     fn handle_signal(&mut self, signal: crate::data::Signal) -> crate::Result<()> {
+        if let Signal::DaemonResponse {
+            req: DaemonRequest::Login | DaemonRequest::Logout | DaemonRequest::SetAccount,
+            ..
+        } = &signal
+        {
+            // the status changed, don't wait for the next fetch
+            self.action_tx
+                .as_ref()
+                .unwrap()
+                .send(Action::GetStatus)
+                .unwrap();
+        }
         match &signal {
             Signal::DaemonPong => {
                 self.last_pong = Some(Instant::now());
             }
+            Signal::DaemonStatus(status) => {
+                let schedule = status.schedule.as_ref().map(describe_schedule);
+                let paused = status.paused.as_ref().map(describe_pause);
+                let portal = status.portal.as_ref().and_then(describe_portal);
+                if schedule != self.schedule || paused != self.paused || portal != self.portal {
+                    self.schedule = schedule;
+                    self.paused = paused;
+                    self.portal = portal;
+                    self.action_tx.as_ref().unwrap().send(Action::Draw).unwrap();
+                }
+            }
             Signal::Exit => {
                 self.exit_state
                     .store(true, std::sync::atomic::Ordering::SeqCst);
@@ -119,6 +188,8 @@ impl Component for Page {
                 let mut com: Box<dyn Component> = match page {
                     AppPage::Menu => Box::<Menu>::default(),
                     AppPage::Form => Box::<AccountForm>::default(),
+                    AppPage::Account => Box::<AccountPage>::default(),
+                    AppPage::Sessions => Box::<SessionsPage>::default(),
                 };
                 com.init()?;
                 com.register_action_sender(self.action_tx.as_ref().unwrap().clone())
@@ -127,15 +198,41 @@ impl Component for Page {
                 self.action_tx.as_ref().unwrap().send(Action::Draw).unwrap();
             }
             Signal::DaemonResponse { req, result } => match req {
+                DaemonRequest::Login => match result {
+                    Ok(_) => self.popup_notification(Level::Info, "登录成功".into()),
+                    Err(e) => self.popup_notification(Level::Error, e.to_string()),
+                },
                 DaemonRequest::SetAccount => match result {
                     Ok(_) => self.popup_notification(Level::Info, "账号信息设置成功".into()),
-                    Err(e) => self.popup_notification(Level::Error, format!("{:?}", e)),
+                    Err(e) => self.popup_notification(Level::Error, e.to_string()),
                 },
                 DaemonRequest::Logout => match result {
                     Ok(_) => self.popup_notification(Level::Info, "登出成功".into()),
-                    Err(e) => self.popup_notification(Level::Error, format!("{:?}", e)),
+                    Err(ClientError::Daemon { error, .. })
+                        if error.code == ErrorCode::NotLoggedIn =>
+                    {
+                        self.popup_notification(Level::Info, "当前未登录".into())
+                    }
+                    Err(e) => self.popup_notification(Level::Error, e.to_string()),
+                },
+                DaemonRequest::AccountInfo => {
+                    if let Err(e) = result {
+                        self.popup_notification(Level::Error, format!("获取账户信息失败: {}", e))
+                    }
+                }
+                DaemonRequest::Sessions => {
+                    if let Err(e) = result {
+                        self.popup_notification(Level::Error, format!("获取在线设备失败: {}", e))
+                    }
+                }
+                DaemonRequest::Kick => match result {
+                    Ok(_) => self.popup_notification(Level::Info, "设备已下线".into()),
+                    Err(e) => self.popup_notification(Level::Error, format!("下线失败: {}", e)),
                 },
             },
+            Signal::AccountInfo(account) if !account.warnings.is_empty() => {
+                self.popup_notification(Level::Warning, account.warnings.join("\n"))
+            }
             _ => (),
         };
         self.inner.handle_signal(signal)?;
@@ -152,6 +249,13 @@ impl Component for Page {
         }
         if new_state != self.connected {
             self.connected = new_state;
+            if new_state {
+                self.action_tx
+                    .as_ref()
+                    .unwrap()
+                    .send(Action::GetStatus)
+                    .unwrap();
+            }
             self.action_tx.as_ref().unwrap().send(Action::Draw).unwrap();
         }
 
@@ -191,6 +295,55 @@ impl Component for Page {
     }
 }
 
+fn describe_schedule(schedule: &ScheduleStatus) -> String {
+    let (now, then) = if schedule.allowed {
+        ("开放中", "暂停")
+    } else {
+        ("已暂停", "开放")
+    };
+    let now_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    match schedule.next_change {
+        Some(at) => format!(
+            "{}，{}后{}",
+            now,
+            format_wait(at.saturating_sub(now_secs)),
+            then
+        ),
+        None => now.to_string(),
+    }
+}
+
+/// Address and access controller, the parts telling where the machine is.
+fn describe_portal(portal: &PortalContext) -> Option<String> {
+    let parts: Vec<_> = [&portal.user_ip, &portal.ac_name, &portal.vlan]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" / "))
+}
+
+fn describe_pause(paused: &LoginPause) -> String {
+    let now_secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    match paused.until {
+        Some(at) => format!(
+            "自动登录已暂停: {}，{}后重试",
+            paused.reason,
+            format_wait(at.saturating_sub(now_secs))
+        ),
+        None => format!(
+            "自动登录已停止: {}，请修改账号或在菜单中选择立即登录",
+            paused.reason
+        ),
+    }
+}
+
 impl Page {
     pub fn popup_notification(&mut self, level: Level, msg: String) {
         self.notification = Some(Notification::new(level, msg));
@@ -0,0 +1,163 @@
+use crossterm::event::{KeyCode, KeyEventKind};
+use ratatui::{
+    layout::Rect,
+    style::{Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Paragraph},
+    Frame,
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use proto::OnlineDevice;
+
+use crate::data::{Action, AppPage, DaemonRequest, Signal};
+
+use super::{util::centered_box_sized, Component, ComponentInfo};
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Loading,
+    Loaded(Vec<OnlineDevice>),
+    Failed,
+}
+
+/// Sessions of the account, any of which can be forced offline.
+#[derive(Default)]
+pub struct SessionsPage {
+    mouse_area: Rect,
+    state: State,
+    selection: usize,
+    /// <Enter> was pressed once on the selection, the next one kicks it
+    confirming: bool,
+    action_tx: Option<UnboundedSender<Action>>,
+}
+
+impl Component for SessionsPage {
+    fn init(&mut self) -> crate::Result<ComponentInfo> {
+        Ok(ComponentInfo::all_enabled())
+    }
+
+    fn register_action_sender(&mut self, sender: UnboundedSender<Action>) -> crate::Result<()> {
+        sender.send(Action::GetSessions).unwrap();
+        self.action_tx = Some(sender);
+        Ok(())
+    }
+
+    fn handle_signal(&mut self, signal: Signal) -> crate::Result<()> {
+        let action_tx = self.action_tx.as_ref().unwrap();
+        match signal {
+            Signal::Sessions(sessions) => {
+                self.selection = self.selection.min(sessions.len().saturating_sub(1));
+                self.state = State::Loaded(sessions);
+            }
+            Signal::DaemonResponse {
+                req: DaemonRequest::Sessions,
+                ..
+            } => self.state = State::Failed,
+            Signal::DaemonResponse {
+                req: DaemonRequest::Kick,
+                result,
+            } => {
+                if result.is_ok() {
+                    self.state = State::Loading;
+                    action_tx.send(Action::GetSessions).unwrap();
+                }
+            }
+            _ => return Ok(()),
+        }
+        action_tx.send(Action::Draw).unwrap();
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: crossterm::event::KeyEvent) -> crate::Result<()> {
+        if key.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        let action_tx = self.action_tx.as_ref().unwrap();
+        let confirming = std::mem::take(&mut self.confirming);
+        let count = match &self.state {
+            State::Loaded(sessions) => sessions.len(),
+            _ => 0,
+        };
+        match key.code {
+            KeyCode::Up if count > 0 => self.selection = (self.selection + count - 1) % count,
+            KeyCode::Down if count > 0 => self.selection = (self.selection + 1) % count,
+            KeyCode::Enter => {
+                let State::Loaded(sessions) = &self.state else {
+                    return Ok(());
+                };
+                let Some(session) = sessions
+                    .get(self.selection)
+                    .and_then(|device| device.session.clone())
+                else {
+                    return Ok(());
+                };
+                if confirming {
+                    action_tx.send(Action::Kick(session)).unwrap();
+                } else {
+                    self.confirming = true;
+                }
+            }
+            KeyCode::Char('r') | KeyCode::Char('R') => {
+                self.state = State::Loading;
+                action_tx.send(Action::GetSessions).unwrap();
+            }
+            KeyCode::Esc => {
+                action_tx.send(Action::JumpTo(AppPage::Menu)).unwrap();
+                return Ok(());
+            }
+            _ => (),
+        }
+        action_tx.send(Action::Draw).unwrap();
+        Ok(())
+    }
+
+    fn draw(&mut self, f: &mut Frame, rect: Rect) -> crate::Result<()> {
+        let block = Block::bordered()
+            .border_style(Style::default().blue())
+            .title("在线设备")
+            .title_bottom(Line::from("<Enter>下线 <R>刷新 <ESC>返回上一级菜单").centered());
+        let area = centered_box_sized(rect, 52, 14).centered;
+        let text = match &self.state {
+            State::Loading => Text::from("正在获取在线设备...".yellow()),
+            State::Failed => Text::from("获取失败，按<R>重试".red()),
+            State::Loaded(sessions) if sessions.is_empty() => Text::from("没有在线设备"),
+            State::Loaded(sessions) => self.session_lines(sessions),
+        };
+        f.render_widget(Paragraph::new(text).block(block), area);
+        self.mouse_area = rect;
+        Ok(())
+    }
+
+    fn mouse_area(&self) -> Rect {
+        self.mouse_area
+    }
+}
+
+impl SessionsPage {
+    fn session_lines(&self, sessions: &[OnlineDevice]) -> Text<'static> {
+        let mut lines: Vec<_> = sessions
+            .iter()
+            .enumerate()
+            .map(|(i, device)| {
+                let line = format!(
+                    "{} {:<15} {}",
+                    if i == self.selection { ">" } else { " " },
+                    device.ip,
+                    device.login_time.as_deref().unwrap_or("登录时间未知")
+                );
+                match (i == self.selection, device.session.is_some()) {
+                    (_, false) => Line::from(line).style(Style::default().dim()),
+                    (true, true) => Line::from(line.green().underlined()),
+                    (false, true) => Line::from(line),
+                }
+            })
+            .collect();
+        if self.confirming {
+            lines.push(Line::from(""));
+            lines.push(Line::from("再按<Enter>确认下线该设备".red().bold()));
+        }
+        Text::from(lines)
+    }
+}
@@ -1,5 +1,3 @@
-use std::usize;
-
 use crossterm::event::MouseEvent;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Position, Rect},
@@ -94,6 +92,12 @@ pub fn mouse_contains(mouse: &MouseEvent, com: &impl Component) -> bool {
 }
 
 pub fn str_to_lines(str: &str, width: u16, style: Style, vec: &mut Vec<Line>) {
+    if str.contains('\n') {
+        for line in str.lines() {
+            str_to_lines(line, width, style, vec);
+        }
+        return;
+    }
     let chars = str.chars().collect::<Vec<char>>();
     let length = chars.len();
     let width = width as usize;
@@ -108,3 +112,14 @@ pub fn str_to_lines(str: &str, width: u16, style: Style, vec: &mut Vec<Line>) {
         curr = end;
     }
 }
+
+/// Rough length of a wait, e.g. `2小时5分钟`.
+pub fn format_wait(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match secs {
+        0..=59 => "不到1分钟".to_string(),
+        60..=3599 => format!("{}分钟", minutes),
+        3600..=86399 => format!("{}小时{}分钟", hours, minutes),
+        _ => format!("{}天{}小时", days, hours),
+    }
+}
@@ -1,105 +1,46 @@
 use std::{error::Error, fmt::Display, io};
 
-use serde::{Deserialize, Serialize};
+use client::ClientError;
+use proto::{Account, OnlineDevice, Status, UserInfo};
 use tokio::time::Instant;
 
 #[derive(Debug)]
 pub enum AppError {
     StdIo(io::Error),
+    /// the daemon is on another machine and no token was given
+    MissingToken(String),
 }
 
 impl Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::StdIo(e) => write!(f, "std io error: {}", e),
+            Self::MissingToken(host) => write!(
+                f,
+                "daemon at {} needs its api token, set {}",
+                host,
+                client::DAEMON_TOKEN_ENV
+            ),
         }
     }
 }
 
 impl Error for AppError {}
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct UserInfo {
-    pub id: String,
-    pub password: String,
-    pub suffix: Suffix,
-}
-
-#[derive(Clone, Copy, Debug)]
-pub enum Suffix {
-    ChinaMobile,
-    ChinaUnicom,
-    ChinaTelecom,
-    Local,
-}
-
-impl Default for Suffix {
-    fn default() -> Self {
-        Self::Local
-    }
-}
-
-impl Serialize for Suffix {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(self.to_str())
-    }
-}
-
-impl Suffix {
-    const CM: &'static str = "@yd";
-    const CU: &'static str = "@lt";
-    const CT: &'static str = "@dx";
-    const LOCAL: &'static str = "@hsd";
-
-    pub fn to_str(&self) -> &str {
-        match self {
-            Suffix::ChinaMobile => Self::CM,
-            Suffix::ChinaUnicom => Self::CU,
-            Suffix::ChinaTelecom => Self::CT,
-            Suffix::Local => Self::LOCAL,
-        }
-    }
-}
-
-impl<'de> Deserialize<'de> for Suffix {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-        match s.as_str() {
-            Self::CM => Ok(Self::ChinaMobile),
-            Self::CU => Ok(Self::ChinaUnicom),
-            Self::CT => Ok(Self::ChinaTelecom),
-            Self::LOCAL => Ok(Self::Local),
-            _ => Err(serde::de::Error::custom("Invalid Suffix")),
-        }
-    }
-}
-
-impl ToString for Suffix {
-    fn to_string(&self) -> String {
-        self.to_str().to_string()
-    }
-}
-
 pub enum AppPage {
     Menu,
     Form,
-}
-
-#[derive(Debug)]
-pub enum DaemonError {
-    Reqwest(reqwest::Error),
-    ErrMessage(serde_json::Value),
+    Account,
+    Sessions,
 }
 
 pub enum DaemonRequest {
+    Login,
     Logout,
     SetAccount,
+    AccountInfo,
+    Sessions,
+    Kick,
 }
 
 pub enum Signal {
@@ -109,15 +50,22 @@ pub enum Signal {
     CheckboxSelected(u16),
     DaemonResponse {
         req: DaemonRequest,
-        result: Result<(), DaemonError>,
+        result: Result<(), ClientError>,
     },
     DaemonPong,
+    DaemonStatus(Box<Status>),
+    AccountInfo(Box<Account>),
+    Sessions(Vec<OnlineDevice>),
     ChangePage(AppPage),
     Exit,
 }
 
 pub enum Action {
     PingDaemon,
+    /// fetch the daemon's status, less often than it is pinged
+    GetStatus,
+    /// log in right away, also lifts a paused auto-login
+    Login,
     Logout,
     JumpTo(AppPage),
     Draw,
@@ -126,11 +74,17 @@ pub enum Action {
     SelectInput(u16),
     SelectCheckbox(u16),
     SetAccount(UserInfo),
+    /// balance and devices, `true` skips the daemon's cache
+    GetAccountInfo(bool),
+    GetSessions,
+    /// force the session with this id offline
+    Kick(String),
 }
 
 #[derive(Debug)]
 pub enum Level {
     Error,
+    Warning,
     Info,
 }
 
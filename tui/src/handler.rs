@@ -1,4 +1,5 @@
 use std::{
+    future::Future,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -6,10 +7,9 @@ use std::{
     time::Duration,
 };
 
+use client::DaemonClient;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use ratatui::layout::Rect;
-use reqwest::Client;
-use serde::Serialize;
 use tokio::{
     sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
     time::{sleep, Instant},
@@ -17,7 +17,7 @@ use tokio::{
 
 use crate::{
     component::Component,
-    data::{Action, AppError, DaemonError, DaemonRequest, Signal, UserInfo},
+    data::{Action, AppError, DaemonRequest, Signal},
     Result, TuiTerminal,
 };
 
@@ -41,6 +41,7 @@ pub async fn term_event_loop(
 pub async fn run_handler(
     component: impl Component,
     terminal: TuiTerminal,
+    client: DaemonClient,
     frame_rate: u16,
     tick_rate: u16,
 ) -> Result<()> {
@@ -50,7 +51,7 @@ pub async fn run_handler(
     let (tick_tx, tick_rx) = mpsc::unbounded_channel();
     tokio::spawn(tick_handler(tick_rate, tick_tx, exit_mark.clone()));
     run_action_handler(
-        terminal, component, tick_rx, event_rx, exit_mark, frame_rate,
+        terminal, component, client, tick_rx, event_rx, exit_mark, frame_rate,
     )
     .await
 }
@@ -66,6 +67,7 @@ async fn tick_handler(tick_rate: u16, tx: UnboundedSender<()>, exit_mark: Arc<At
 async fn run_action_handler(
     mut terminal: TuiTerminal,
     mut component: impl Component,
+    client: DaemonClient,
     mut tick_rx: UnboundedReceiver<()>,
     mut event_rx: UnboundedReceiver<Event>,
     exit_mark: Arc<AtomicBool>,
@@ -134,47 +136,83 @@ async fn run_action_handler(
                 }
                 Action::Quit => signal_tx.send(Signal::Exit).unwrap(),
                 Action::PingDaemon => {
-                    let signal_tx = signal_tx.clone();
+                    let (client, signal_tx) = (client.clone(), signal_tx.clone());
                     tokio::spawn(async move {
-                        if let Ok(resp) = reqwest::get("http://127.0.0.1:11451/").await {
-                            if resp.status().is_success() {
-                                signal_tx.send(Signal::DaemonPong).unwrap();
-                            }
+                        if client.ping().await.is_ok() {
+                            signal_tx.send(Signal::DaemonPong).unwrap();
+                        }
+                    });
+                }
+                Action::GetStatus => {
+                    let (client, signal_tx) = (client.clone(), signal_tx.clone());
+                    tokio::spawn(async move {
+                        if let Ok(status) = client.status().await {
+                            signal_tx
+                                .send(Signal::DaemonStatus(Box::new(status)))
+                                .unwrap();
                         }
                     });
                 }
                 Action::GetAccount => {
-                    let signal_tx = signal_tx.clone();
+                    let (client, signal_tx) = (client.clone(), signal_tx.clone());
                     tokio::spawn(async move {
-                        if let Ok(resp) = reqwest::get("http://127.0.0.1:11451/user").await {
-                            if let Ok(user) = resp.json::<UserInfo>().await {
-                                signal_tx.send(Signal::UserInfo(user)).unwrap();
-                            }
+                        if let Ok(Some(user)) = client.get_user().await {
+                            signal_tx.send(Signal::UserInfo(user)).unwrap();
                         }
                     });
                 }
                 Action::SelectInput(id) => signal_tx.send(Signal::InputSelected(id)).unwrap(),
                 Action::SelectCheckbox(id) => signal_tx.send(Signal::CheckboxSelected(id)).unwrap(),
                 Action::SetAccount(user) => {
-                    send_daemon_request(
-                        "http://127.0.0.1:11451/user",
-                        Some(user),
-                        signal_tx.clone(),
-                        DaemonRequest::SetAccount,
-                        true,
-                    )
-                    .await
+                    let client = client.clone();
+                    send_daemon_request(signal_tx.clone(), DaemonRequest::SetAccount, async move {
+                        client.set_user(&user).await
+                    })
+                }
+                Action::GetAccountInfo(refresh) => {
+                    let (client, signal_tx) = (client.clone(), signal_tx.clone());
+                    tokio::spawn(async move {
+                        let signal = match client.account(refresh).await {
+                            Ok(account) => Signal::AccountInfo(Box::new(account)),
+                            Err(e) => Signal::DaemonResponse {
+                                req: DaemonRequest::AccountInfo,
+                                result: Err(e),
+                            },
+                        };
+                        signal_tx.send(signal).unwrap();
+                    });
+                }
+                Action::GetSessions => {
+                    let (client, signal_tx) = (client.clone(), signal_tx.clone());
+                    tokio::spawn(async move {
+                        let signal = match client.sessions().await {
+                            Ok(sessions) => Signal::Sessions(sessions),
+                            Err(e) => Signal::DaemonResponse {
+                                req: DaemonRequest::Sessions,
+                                result: Err(e),
+                            },
+                        };
+                        signal_tx.send(signal).unwrap();
+                    });
+                }
+                Action::Kick(session) => {
+                    let client = client.clone();
+                    send_daemon_request(signal_tx.clone(), DaemonRequest::Kick, async move {
+                        client.kick(&session).await
+                    })
                 }
                 Action::JumpTo(page) => signal_tx.send(Signal::ChangePage(page)).unwrap(),
+                Action::Login => {
+                    let client = client.clone();
+                    send_daemon_request(signal_tx.clone(), DaemonRequest::Login, async move {
+                        client.login().await
+                    })
+                }
                 Action::Logout => {
-                    send_daemon_request::<UserInfo>(
-                        "http://127.0.0.1:11451/logout",
-                        None,
-                        signal_tx.clone(),
-                        DaemonRequest::Logout,
-                        false,
-                    )
-                    .await;
+                    let client = client.clone();
+                    send_daemon_request(signal_tx.clone(), DaemonRequest::Logout, async move {
+                        client.logout().await
+                    })
                 }
             };
         }
@@ -194,58 +232,15 @@ async fn handle_key(key: KeyEvent) -> Option<Signal> {
     }
 }
 
-async fn send_daemon_request<S: Serialize + Send + Sync + 'static>(
-    url: &str,
-    json: Option<S>,
+fn send_daemon_request(
     signal_tx: UnboundedSender<Signal>,
-    req_type: DaemonRequest,
-    post: bool,
+    req: DaemonRequest,
+    fut: impl Future<Output = client::Result<()>> + Send + 'static,
 ) {
-    let url = url.to_owned();
     tokio::spawn(async move {
-        let client = Client::default();
-        let res = if post {
-            match json {
-                Some(j) => client.post(url).json(&j),
-                None => client.post(url),
-            }
-        } else {
-            client.get(url)
-        }
-        .send()
-        .await;
-        match res {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    signal_tx
-                        .send(Signal::DaemonResponse {
-                            req: req_type,
-                            result: Ok(()),
-                        })
-                        .unwrap();
-                } else {
-                    match resp.json::<serde_json::Value>().await {
-                        Ok(json) => signal_tx
-                            .send(Signal::DaemonResponse {
-                                req: req_type,
-                                result: Err(DaemonError::ErrMessage(json)),
-                            })
-                            .unwrap(),
-                        Err(e) => signal_tx
-                            .send(Signal::DaemonResponse {
-                                req: req_type,
-                                result: Err(DaemonError::Reqwest(e)),
-                            })
-                            .unwrap(),
-                    };
-                }
-            }
-            Err(e) => signal_tx
-                .send(Signal::DaemonResponse {
-                    req: req_type,
-                    result: Err(DaemonError::Reqwest(e)),
-                })
-                .unwrap(),
-        };
+        let result = fut.await;
+        signal_tx
+            .send(Signal::DaemonResponse { req, result })
+            .unwrap();
     });
 }
@@ -7,6 +7,7 @@ use std::{
     panic, result,
 };
 
+use client::DaemonClient;
 use component::container::AppContainer;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
@@ -42,8 +43,19 @@ fn shutdown() -> io::Result<()> {
     Ok(())
 }
 
-pub async fn run(frame_rate: u16, tick_rate: u16) -> Result<()> {
+/// Run the tui against the daemon `client` talks to, see
+/// [`DaemonClient::discover`] for the local one.
+pub async fn run(client: DaemonClient, frame_rate: u16, tick_rate: u16) -> Result<()> {
+    let mut container = AppContainer::default();
+    // a daemon listening on the LAN, over http or https, refuses requests
+    // without its token
+    if !client.is_local() {
+        if !client.has_token() {
+            return Err(AppError::MissingToken(client.host().to_string()));
+        }
+        container = container.with_remote(client.host());
+    }
     let terminal = startup().map_err(AppError::StdIo)?;
-    run_handler(AppContainer::default(), terminal, frame_rate, tick_rate).await?;
+    run_handler(container, terminal, client, frame_rate, tick_rate).await?;
     shutdown().map_err(AppError::StdIo)
 }